@@ -6,16 +6,19 @@ extern crate minecraft_mappings_engine as engine;
 extern crate clap;
 
 use std::path::PathBuf;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 use std::fs::{self, File};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::process;
 
-use failure::Error;
-use srglib::prelude::*;
+use failure::{Error, format_err};
 
-use mappings::cache::MinecraftMappingsCache;
-use mappings::{McpVersion, McpVersionSpec, MinecraftVersion};
-use engine::{TargetMapping, MappingsTargetComputer};
+use mappings::cache::{MinecraftMappingsCache, CacheSettings};
+use mappings::mirrors::MirrorConfig;
+use mappings::maintenance::{CacheDirectory, PrunePolicy};
+use mappings::warm::WarmTask;
+use mappings::{McpVersionSpec, McpVersionRequest, MinecraftVersion, SpigotRevision, YarnVersion};
+use engine::{TargetMapping, MappingSystem, MappingsTargetComputer, OutputFormat};
 
 fn app() -> clap::App<'static, 'static> {
     clap_app!(minecraft_mappings =>
@@ -23,15 +26,110 @@ fn app() -> clap::App<'static, 'static> {
         (author: crate_authors!())
         (about: crate_description!())
         (@arg output_dir: --out +takes_value default_value[out] "The output directory to place mappings")
-        (@arg mcp_version: --mcp +takes_value "The MCP version to generate mappings for")
+        (@arg mcp_version: --mcp +takes_value "The MCP version to generate mappings for, like snapshot_20180925, snapshot_latest or stable_nodoc_before_2018-10-01")
+        (@arg cross_version_mcp: --("cross-version-mcp") "Allow a MCP version exported for a different minecraft version")
+        (@arg spigot_revision: --spigot +takes_value "The revision of spigot's mappings to use, either a spigot build number or a BuildData commit")
+        (@arg yarn_version: --yarn +takes_value "The yarn build to use, like 1.14.4+build.18, which defaults to the latest one")
+        (@arg format: --format +takes_value default_value[srg] possible_value[srg tsrg] "The format to output the mappings in")
         (@arg cache: --cache +takes_value default_value[cache] "Specify an alternate cache location")
+        (@arg offline: --offline "Never access the network, using only the cached data")
+        (@arg mirrors: --mirrors +takes_value "A JSON file specifying the mirrors to fetch data from")
         (@arg minecraft_version: +required "The minecraft version to generate the mappings for")
         (@arg targets: +required +multiple "The target mappings to generate")
+        (@setting SubcommandsNegateReqs)
+        (@subcommand cache =>
+            (about: "Manage the contents of the cache")
+            (@setting SubcommandRequiredElseHelp)
+            (@subcommand list =>
+                (about: "List the cached versions with their sizes and ages"))
+            (@subcommand verify =>
+                (about: "Check the cached versions for corruption"))
+            (@subcommand prune =>
+                (about: "Remove cached versions, which will be downloaded again when needed")
+                (@arg older_than: --("older-than") +takes_value "Remove versions cached more than this many days ago")
+                (@arg versions: --("minecraft-version") +takes_value +multiple "Remove everything cached for this minecraft version")
+                (@arg max_size: --("max-size") +takes_value "Remove the oldest versions until the cache fits in this size (e.g. 500M)")
+                (@arg invalid: --invalid "Remove versions that fail verification"))
+        )
+        (@subcommand access_transforms =>
+            (about: "Translate spigot's access transformers into a forge-style access transformer")
+            (@arg minecraft_version: +required "The minecraft version to translate the access transformers for")
+            (@arg system: +required "The mapping system to translate into, like mcp")
+        )
+        (@subcommand spigot_revisions =>
+            (about: "List the revisions of spigot's mappings in BuildData")
+            (@arg minecraft_version: "Only list the revisions for this minecraft version")
+        )
+        (@subcommand import_mojang =>
+            (about: "Use local ProGuard files as mojang's mappings for a minecraft version")
+            (@arg minecraft_version: +required "The minecraft version the mappings are for")
+            (@arg files: +required +multiple "The client and/or server mapping files")
+        )
+        (@subcommand identify =>
+            (about: "Identify the minecraft version and spigot revisions of a vanilla server jar")
+            (@arg server_jar: +required "The server jar to identify")
+            (@arg revision: --verify +takes_value "Only check the jar matches this spigot revision")
+        )
+        (@subcommand warm =>
+            (about: "Download many versions into the cache in parallel")
+            (@arg jobs: -j --jobs +takes_value default_value[4] "The number of versions to download at once")
+            (@arg mcp: --mcp +takes_value +multiple "The MCP versions to download")
+            (@arg all_mcp: --("all-mcp") "Download srg data and every MCP version known to MCPBot")
+            (@arg nodoc: --nodoc "With --all-mcp, download the nodoc exports instead of the documented ones")
+            (@arg all_spigot: --("all-spigot") "Download every spigot version")
+            (@arg versions: +multiple "The minecraft versions to download srg and spigot data for")
+        )
     )
 }
 
 fn main() -> Result<(), Error> {
     let matches = app().get_matches();
+    let cache_location = PathBuf::from(matches.value_of("cache").unwrap());
+    match matches.subcommand() {
+        ("cache", Some(matches)) => cache_command(CacheDirectory::new(cache_location), matches),
+        ("access_transforms", Some(at_matches)) => {
+            let cache = setup_cache(cache_location, &matches)?;
+            access_transforms_command(&cache, &matches, at_matches)
+        },
+        ("spigot_revisions", Some(revision_matches)) => {
+            let cache = setup_cache(cache_location, &matches)?;
+            spigot_revisions_command(&cache, revision_matches)
+        },
+        ("import_mojang", Some(import_matches)) => {
+            let cache = setup_cache(cache_location, &matches)?;
+            let minecraft_version = value_t!(import_matches, "minecraft_version", MinecraftVersion)
+                .unwrap_or_else(|e| e.exit());
+            let files: Vec<PathBuf> = import_matches.values_of("files").unwrap()
+                .map(PathBuf::from).collect();
+            cache.import_mojang_mappings(minecraft_version, &files)?;
+            println!("Imported mojang's mappings for {}", minecraft_version);
+            Ok(())
+        },
+        ("identify", Some(identify_matches)) => {
+            let cache = setup_cache(cache_location, &matches)?;
+            identify_command(&cache, identify_matches)
+        },
+        ("warm", Some(warm_matches)) => {
+            let cache = setup_cache(cache_location, &matches)?;
+            warm_command(&cache, warm_matches)
+        },
+        _ => generate(cache_location, &matches)
+    }
+}
+fn setup_cache(cache_location: PathBuf, matches: &clap::ArgMatches) -> Result<MinecraftMappingsCache, Error> {
+    fs::create_dir_all(&cache_location)?;
+    let mirrors = match matches.value_of("mirrors") {
+        Some(location) => MirrorConfig::load(location.as_ref())?,
+        None => MirrorConfig::default()
+    };
+    let settings = CacheSettings {
+        offline: matches.is_present("offline"),
+        mirrors,
+        ..CacheSettings::default()
+    };
+    MinecraftMappingsCache::setup_with(cache_location, settings)
+}
+fn generate(cache_location: PathBuf, matches: &clap::ArgMatches) -> Result<(), Error> {
     let targets: Vec<TargetMapping> = values_t!(matches, "targets", TargetMapping)
         .unwrap_or_else(|e| e.exit());
     let minecraft_version = value_t!(matches, "minecraft_version", MinecraftVersion)
@@ -39,30 +137,277 @@ fn main() -> Result<(), Error> {
     // Demand a MCP version (if needed)
     let needs_mcp_version = targets.iter()
         .any(TargetMapping::needs_mcp_version);
-    let mcp_version: Option<McpVersion> = if needs_mcp_version {
-        Some(value_t!(matches, "mcp_version", McpVersionSpec)
-            .unwrap_or_else(|e| e.exit()).version)
+    let mcp_request: Option<McpVersionRequest> = if needs_mcp_version {
+        Some(value_t!(matches, "mcp_version", McpVersionRequest)
+            .unwrap_or_else(|e| e.exit()))
     } else {
         None
     };
-    let cache_location = PathBuf::from(matches.value_of("cache").unwrap());
+    let format = value_t!(matches, "format", OutputFormat).unwrap_or_else(|e| e.exit());
     let out = PathBuf::from(matches.value_of("output_dir").unwrap());
-    fs::create_dir_all(&cache_location)?;
     fs::create_dir_all(&out)?;
-    let cache = MinecraftMappingsCache::setup(cache_location.clone())?;
+    let cache = setup_cache(cache_location, matches)?;
+    let mcp_version = resolve_mcp_version(&cache, mcp_request, minecraft_version)?;
+    let yarn_version = if targets.iter().any(TargetMapping::needs_yarn_version) {
+        Some(resolve_yarn_version(&cache, matches, minecraft_version)?)
+    } else {
+        None
+    };
     let start = Instant::now();
-    let computer = MappingsTargetComputer::new(&cache, minecraft_version, mcp_version);
+    let cross_version_mcp = matches.is_present("cross_version_mcp");
+    let computer = MappingsTargetComputer::new(&cache, minecraft_version, mcp_version)
+        .cross_version_mcp(cross_version_mcp)
+        .spigot_revision(spigot_revision(matches))
+        .yarn_version(yarn_version);
     for &target in &targets {
-        let out_location = out.join(format!("{}.srg", target));
+        let out_location = out.join(format!("{}.{}", target, format.extension()));
         let target_start = Instant::now();
         let mappings = computer.compute_target(target)?;
         let writer = BufWriter::new(File::create(out_location)?);
-        SrgMappingsFormat::write(&mappings, writer)?;
+        format.write(&mappings, writer)?;
+        // SRG can't hold the MCP documentation or parameter names, so we write them alongside the mappings
+        if target.needs_mcp_version() {
+            let metadata = computer.compute_metadata(target)?;
+            if !metadata.is_empty() {
+                let writer = BufWriter::new(File::create(out.join(format!("{}.json", target)))?);
+                ::serde_json::to_writer_pretty(writer, &metadata)?;
+            }
+        }
         println!("  Finished {} in {}ms", target, duration_to_millis(target_start.elapsed()));
     }
     println!("Finished {} targets in {}ms", targets.len(), duration_to_millis(start.elapsed()));
+    if cross_version_mcp {
+        if let Some(missing) = computer.missing_mcp_names() {
+            println!("Warning: {} srg names had no MCP name", missing);
+        }
+    }
+    Ok(())
+}
+fn resolve_mcp_version(
+    cache: &MinecraftMappingsCache,
+    request: Option<McpVersionRequest>,
+    minecraft_version: MinecraftVersion
+) -> Result<Option<McpVersionSpec>, Error> {
+    Ok(match request {
+        Some(request) => {
+            let spec = cache.resolve_mcp_version(request, minecraft_version)?;
+            if request.exact().is_none() {
+                println!("Resolved {} to {}", request, spec);
+            }
+            Some(spec)
+        },
+        None => None
+    })
+}
+fn resolve_yarn_version(
+    cache: &MinecraftMappingsCache,
+    matches: &clap::ArgMatches,
+    minecraft_version: MinecraftVersion
+) -> Result<YarnVersion, Error> {
+    if matches.is_present("yarn_version") {
+        Ok(value_t!(matches, "yarn_version", YarnVersion).unwrap_or_else(|e| e.exit()))
+    } else {
+        let version = cache.resolve_yarn_version(minecraft_version)?;
+        println!("Resolved yarn to {}", version);
+        Ok(version)
+    }
+}
+fn spigot_revision(matches: &clap::ArgMatches) -> Option<SpigotRevision> {
+    if matches.is_present("spigot_revision") {
+        Some(value_t!(matches, "spigot_revision", SpigotRevision).unwrap_or_else(|e| e.exit()))
+    } else {
+        None
+    }
+}
+fn spigot_revisions_command(cache: &MinecraftMappingsCache, matches: &clap::ArgMatches) -> Result<(), Error> {
+    let version = if matches.is_present("minecraft_version") {
+        Some(value_t!(matches, "minecraft_version", MinecraftVersion).unwrap_or_else(|e| e.exit()))
+    } else {
+        None
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let revisions = cache.known_spigot_revisions(version)?;
+    for revision in &revisions {
+        println!(
+            "{} {:<8} {:>5} days old  {}",
+            &revision.commit[..10],
+            format!("{}", revision.minecraft_version),
+            (now - revision.time).max(0) as u64 / SECONDS_PER_DAY,
+            revision.summary
+        );
+    }
+    println!("Found {} revisions", revisions.len());
+    Ok(())
+}
+fn identify_command(cache: &MinecraftMappingsCache, matches: &clap::ArgMatches) -> Result<(), Error> {
+    let server_jar = PathBuf::from(matches.value_of("server_jar").unwrap());
+    if matches.is_present("revision") {
+        let revision = value_t!(matches, "revision", SpigotRevision).unwrap_or_else(|e| e.exit());
+        cache.verify_server_jar(&revision, &server_jar)?;
+        println!("{} matches spigot {}", server_jar.display(), revision);
+        return Ok(())
+    }
+    let revisions = cache.identify_server_jar(&server_jar)?;
+    if revisions.is_empty() {
+        println!("{} doesn't match any spigot revision", server_jar.display());
+        process::exit(1);
+    }
+    println!("{} is minecraft {}", server_jar.display(), revisions[0].minecraft_version);
+    for revision in &revisions {
+        println!("  Matches spigot {}: {}", &revision.commit[..10], revision.summary);
+    }
+    Ok(())
+}
+fn access_transforms_command(
+    cache: &MinecraftMappingsCache,
+    matches: &clap::ArgMatches,
+    at_matches: &clap::ArgMatches
+) -> Result<(), Error> {
+    let minecraft_version = value_t!(at_matches, "minecraft_version", MinecraftVersion)
+        .unwrap_or_else(|e| e.exit());
+    let system = value_t!(at_matches, "system", MappingSystem).unwrap_or_else(|e| e.exit());
+    let mcp_request = if system.is_mcp() {
+        Some(value_t!(matches, "mcp_version", McpVersionRequest).unwrap_or_else(|e| e.exit()))
+    } else {
+        None
+    };
+    let mcp_version = resolve_mcp_version(cache, mcp_request, minecraft_version)?;
+    let computer = MappingsTargetComputer::new(cache, minecraft_version, mcp_version)
+        .cross_version_mcp(matches.is_present("cross_version_mcp"))
+        .spigot_revision(spigot_revision(matches));
+    let transforms = computer.compute_access_transforms(system)?;
+    let out = PathBuf::from(matches.value_of("output_dir").unwrap());
+    fs::create_dir_all(&out)?;
+    let out_location = out.join(format!("spigot2{}_at.cfg", system));
+    let mut writer = BufWriter::new(File::create(&out_location)?);
+    writeln!(writer, "# Spigot's access transformers for minecraft {}", minecraft_version)?;
+    for transform in &transforms {
+        writeln!(writer, "{}", transform.to_forge_line())?;
+    }
+    println!("Wrote {} access transformers to {}", transforms.len(), out_location.display());
+    Ok(())
+}
+fn warm_command(cache: &MinecraftMappingsCache, matches: &clap::ArgMatches) -> Result<(), Error> {
+    let jobs = value_t!(matches, "jobs", usize).unwrap_or_else(|e| e.exit());
+    let mut tasks = Vec::new();
+    if matches.is_present("versions") {
+        for version in values_t!(matches, "versions", MinecraftVersion).unwrap_or_else(|e| e.exit()) {
+            tasks.push(WarmTask::Srg(version));
+            tasks.push(WarmTask::Spigot(version));
+        }
+    }
+    if matches.is_present("mcp") {
+        for spec in values_t!(matches, "mcp", McpVersionSpec).unwrap_or_else(|e| e.exit()) {
+            tasks.push(WarmTask::Mcp(spec));
+        }
+    }
+    if matches.is_present("all_mcp") {
+        tasks.extend(cache.known_minecraft_versions().into_iter().map(WarmTask::Srg));
+        // Requests use the documented exports unless they ask for nodoc, so that's what we warm by default
+        let nodoc = matches.is_present("nodoc");
+        tasks.extend(cache.known_mcp_versions().into_iter()
+            .map(|version| WarmTask::Mcp(version.create_spec(nodoc))));
+    }
+    if matches.is_present("all_spigot") {
+        tasks.extend(cache.known_spigot_versions()?.into_iter().map(WarmTask::Spigot));
+    }
+    let start = Instant::now();
+    let report = cache.warm(tasks, jobs, |progress| {
+        match progress.result {
+            Ok(()) => println!("[{}/{}] Fetched {}", progress.completed, progress.total, progress.task),
+            Err(e) => println!("[{}/{}] Failed to fetch {}: {}", progress.completed, progress.total, progress.task, e),
+        }
+    });
+    println!(
+        "Fetched {} versions in {}ms",
+        report.succeeded.len(),
+        duration_to_millis(start.elapsed())
+    );
+    if !report.failed.is_empty() {
+        println!("Failed to fetch {} versions", report.failed.len());
+        process::exit(1);
+    }
     Ok(())
 }
+fn cache_command(directory: CacheDirectory, matches: &clap::ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("list", Some(_)) => {
+            let entries = directory.list_entries()?;
+            for entry in &entries {
+                println!(
+                    "{:<32} {:>10} {:>5} days old",
+                    format!("{}", entry.kind),
+                    format_size(entry.size),
+                    entry.age().as_secs() / SECONDS_PER_DAY
+                );
+            }
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            println!("Total cache size {}", format_size(total));
+        },
+        ("verify", Some(_)) => {
+            let corrupted = directory.verify()?;
+            for entry in &corrupted {
+                println!("Corrupted {} at {}", entry.kind, entry.location.display());
+            }
+            if corrupted.is_empty() {
+                println!("All cached versions are valid");
+            } else {
+                println!("Found {} corrupted versions", corrupted.len());
+                process::exit(1);
+            }
+        },
+        ("prune", Some(matches)) => {
+            let max_age = if matches.is_present("older_than") {
+                let days = value_t!(matches, "older_than", u64).unwrap_or_else(|e| e.exit());
+                Some(Duration::from_secs(days * SECONDS_PER_DAY))
+            } else {
+                None
+            };
+            let versions = if matches.is_present("versions") {
+                values_t!(matches, "versions", MinecraftVersion).unwrap_or_else(|e| e.exit())
+            } else {
+                Vec::new()
+            };
+            let max_size = match matches.value_of("max_size") {
+                Some(size) => Some(parse_size(size)
+                    .ok_or_else(|| format_err!("Invalid size {:?}", size))?),
+                None => None
+            };
+            let policy = PrunePolicy { max_age, versions, max_size, invalid: matches.is_present("invalid") };
+            let report = directory.prune(&policy)?;
+            for entry in &report.removed {
+                println!("Removed {} ({})", entry.kind, format_size(entry.size));
+            }
+            println!(
+                "Freed {}, with {} remaining",
+                format_size(report.freed),
+                format_size(report.remaining)
+            );
+        },
+        _ => unreachable!()
+    }
+    Ok(())
+}
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const SIZE_SUFFIXES: [(char, u64); 3] = [('K', 1 << 10), ('M', 1 << 20), ('G', 1 << 30)];
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    for &(suffix, multiplier) in &SIZE_SUFFIXES {
+        if s.ends_with(suffix) || s.ends_with(suffix.to_ascii_lowercase()) {
+            let value: u64 = s[..(s.len() - 1)].parse().ok()?;
+            return value.checked_mul(multiplier)
+        }
+    }
+    s.parse().ok()
+}
+fn format_size(size: u64) -> String {
+    for &(suffix, multiplier) in SIZE_SUFFIXES.iter().rev() {
+        if size >= multiplier {
+            return format!("{:.1}{}iB", size as f64 / multiplier as f64, suffix)
+        }
+    }
+    format!("{}B", size)
+}
 fn duration_to_millis(duration: Duration) -> u64 {
     duration.as_secs().saturating_mul(1000)
         .saturating_add(duration.subsec_millis().into())
@@ -0,0 +1,208 @@
+//! Access transformers, which change the access of classes and members.
+//!
+//! Both MCP and spigot publish these, although they each have their own file format.
+use std::str::FromStr;
+use std::fmt::{self, Display, Formatter};
+
+use failure_derive::Fail;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLevel {
+    Private,
+    Default,
+    Protected,
+    Public
+}
+impl FromStr for AccessLevel {
+    type Err = InvalidAccessTransform;
+
+    fn from_str(s: &str) -> Result<AccessLevel, InvalidAccessTransform> {
+        // MCP uses uppercase, while spigot uses lowercase
+        Ok(match &*s.to_ascii_lowercase() {
+            "private" => AccessLevel::Private,
+            "default" => AccessLevel::Default,
+            "protected" => AccessLevel::Protected,
+            "public" => AccessLevel::Public,
+            _ => return Err(InvalidAccessTransform(s.into()))
+        })
+    }
+}
+impl Display for AccessLevel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AccessLevel::Private => "private",
+            AccessLevel::Default => "default",
+            AccessLevel::Protected => "protected",
+            AccessLevel::Public => "public",
+        })
+    }
+}
+
+/// The new access of a class or member
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AccessChange {
+    pub level: AccessLevel,
+    /// Whether to add (`Some(true)`) or remove (`Some(false)`) the final modifier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finality: Option<bool>
+}
+impl FromStr for AccessChange {
+    type Err = InvalidAccessTransform;
+
+    /// Parse the access using the convention of `public-f` to remove final and `public+f` to add it
+    fn from_str(s: &str) -> Result<AccessChange, InvalidAccessTransform> {
+        let (level, finality) = if s.ends_with("-f") || s.ends_with("-F") {
+            (&s[..(s.len() - 2)], Some(false))
+        } else if s.ends_with("+f") || s.ends_with("+F") {
+            (&s[..(s.len() - 2)], Some(true))
+        } else {
+            (s, None)
+        };
+        Ok(AccessChange { level: level.parse()?, finality })
+    }
+}
+impl Display for AccessChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.level)?;
+        match self.finality {
+            None => Ok(()),
+            Some(true) => f.write_str("+f"),
+            Some(false) => f.write_str("-f"),
+        }
+    }
+}
+
+/// The class or member whose access is changed, using internal names
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessTarget {
+    Class(String),
+    Field {
+        class: String,
+        name: String
+    },
+    Method {
+        class: String,
+        name: String,
+        descriptor: String
+    }
+}
+impl AccessTarget {
+    #[inline]
+    pub fn class(&self) -> &str {
+        match *self {
+            AccessTarget::Class(ref class) |
+            AccessTarget::Field { ref class, .. } |
+            AccessTarget::Method { ref class, .. } => class,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AccessTransform {
+    pub change: AccessChange,
+    pub target: AccessTarget
+}
+impl AccessTransform {
+    /// Parse a line of the form `{access} {class} [{member} [{descriptor}]]`
+    ///
+    /// Methods may have their descriptor attached to their name, like `foo(I)V`.
+    pub fn parse_line(line: &str) -> Result<AccessTransform, InvalidAccessTransform> {
+        let error = || InvalidAccessTransform(line.into());
+        let mut parts = line.split_whitespace();
+        let change = parts.next().ok_or_else(error)?.parse::<AccessChange>()?;
+        let class = String::from(parts.next().ok_or_else(error)?);
+        let target = match (parts.next(), parts.next()) {
+            (None, _) => AccessTarget::Class(class),
+            (Some(member), Some(descriptor)) => AccessTarget::Method {
+                class, name: member.into(), descriptor: descriptor.into()
+            },
+            (Some(member), None) => match member.find('(') {
+                Some(index) => AccessTarget::Method {
+                    class,
+                    name: member[..index].into(),
+                    descriptor: member[index..].into()
+                },
+                None => AccessTarget::Field { class, name: member.into() }
+            }
+        };
+        if parts.next().is_some() { return Err(error()) }
+        Ok(AccessTransform { change, target })
+    }
+    /// Format the transform as a line of a forge access transformer,
+    /// which uses binary class names like `net.minecraft.world.World`.
+    pub fn to_forge_line(&self) -> String {
+        match self.target {
+            AccessTarget::Class(ref class) => {
+                format!("{} {}", self.change, class.replace('/', "."))
+            },
+            AccessTarget::Field { ref class, ref name } => {
+                format!("{} {} {}", self.change, class.replace('/', "."), name)
+            },
+            AccessTarget::Method { ref class, ref name, ref descriptor } => {
+                format!("{} {} {}{}", self.change, class.replace('/', "."), name, descriptor)
+            },
+        }
+    }
+}
+
+/// Parse all the access transforms in a file, skipping blank lines and `#` comments
+pub fn parse_access_transforms(text: &str) -> Result<Vec<AccessTransform>, InvalidAccessTransform> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(AccessTransform::parse_line)
+        .collect()
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid access transform {:?}", _0)]
+pub struct InvalidAccessTransform(String);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn parse_transforms() {
+        let transforms = parse_access_transforms(
+            "# Comment\n\
+            PUBLIC net/minecraft/block/Block\n\
+            public-f net/minecraft/server/Entity field_70165_t\n\
+            protected net/minecraft/server/Entity func_70105_a (FF)V\n\
+            public net/minecraft/server/World a(I)Z # Trailing comment\n"
+        ).unwrap();
+        assert_eq!(transforms, vec![
+            AccessTransform {
+                change: AccessChange { level: AccessLevel::Public, finality: None },
+                target: AccessTarget::Class("net/minecraft/block/Block".into())
+            },
+            AccessTransform {
+                change: AccessChange { level: AccessLevel::Public, finality: Some(false) },
+                target: AccessTarget::Field {
+                    class: "net/minecraft/server/Entity".into(),
+                    name: "field_70165_t".into()
+                }
+            },
+            AccessTransform {
+                change: AccessChange { level: AccessLevel::Protected, finality: None },
+                target: AccessTarget::Method {
+                    class: "net/minecraft/server/Entity".into(),
+                    name: "func_70105_a".into(),
+                    descriptor: "(FF)V".into()
+                }
+            },
+            AccessTransform {
+                change: AccessChange { level: AccessLevel::Public, finality: None },
+                target: AccessTarget::Method {
+                    class: "net/minecraft/server/World".into(),
+                    name: "a".into(),
+                    descriptor: "(I)Z".into()
+                }
+            },
+        ]);
+        assert_eq!(transforms[1].to_forge_line(), "public-f net.minecraft.server.Entity field_70165_t");
+        assert_eq!(transforms[2].to_forge_line(), "protected net.minecraft.server.Entity func_70105_a(FF)V");
+    }
+}
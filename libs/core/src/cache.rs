@@ -1,39 +1,295 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::sync::Arc;
+use std::fmt::{self, Debug, Formatter};
 
-use failure::Error;
+use failure::{Error, format_err};
+use failure_derive::Fail;
+use serde_derive::Serialize;
 use srglib::prelude::*;
 
 use crate::MinecraftVersion;
-use crate::spigot::{SpigotMappingsCache, SpigotMappings};
-use crate::mcp::{McpVersionCache, McpMappings, McpVersion};
+use crate::mojang::MojangMappingsCache;
+use crate::fabric::{FabricMappingsCache, YarnVersion};
+use crate::spigot::{SpigotMappingsCache, SpigotMappings, SpigotRevision, SpigotRevisionInfo};
+use crate::mcp::{McpVersionCache, McpMappings, McpVersion, McpVersionSpec, McpVersionRequest, SrgFormat};
+use crate::utils::{Downloader, LoadingCache, LruCache};
+pub use crate::utils::{LruStats, SharedError, find_cause};
+use crate::transport::{Transport, CurlTransport};
+use crate::mirrors::MirrorConfig;
+use crate::maintenance::CacheDirectory;
+use crate::warm::{self, WarmTask, WarmReport, WarmProgress};
+use crate::mapped::MappedMappings;
+use crate::mcp_config::McpConfig;
+
+/// Controls how the `MinecraftMappingsCache` fetches its data
+#[derive(Clone)]
+pub struct CacheSettings {
+    /// Never access the network, serving everything from the cache directory.
+    ///
+    /// Anything that isn't already cached fails with `NotCachedOffline`.
+    /// Cached files without a valid manifest fail with `UnverifiedCacheOffline`.
+    pub offline: bool,
+    /// The transport we use to access upstream data, which defaults to curl
+    pub transport: Arc<dyn Transport>,
+    /// The mirrors we fetch upstream data from
+    pub mirrors: MirrorConfig
+}
+impl Default for CacheSettings {
+    #[inline]
+    fn default() -> Self {
+        CacheSettings {
+            offline: false,
+            transport: Arc::new(CurlTransport),
+            mirrors: MirrorConfig::default()
+        }
+    }
+}
+impl Debug for CacheSettings {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CacheSettings")
+            .field("offline", &self.offline)
+            .field("mirrors", &self.mirrors)
+            .finish()
+    }
+}
+
+/// The total size of the mapped stores we keep open
+///
+/// They're only in the page cache rather than on the heap, but each one still holds a file and its address space.
+const MAPPED_CACHE_BUDGET: usize = 1024 * 1024 * 1024;
 
 pub struct MinecraftMappingsCache {
+    location: PathBuf,
     spigot: SpigotMappingsCache,
-    mcp: McpVersionCache
+    mcp: McpVersionCache,
+    mojang: MojangMappingsCache,
+    fabric: FabricMappingsCache,
+    /// The mapped stores we've already opened, keyed by their location
+    mapped: LoadingCache<LruCache<PathBuf, Arc<MappedMappings>>>
 }
 impl MinecraftMappingsCache {
+    #[inline]
     pub fn setup(location: PathBuf) -> Result<MinecraftMappingsCache, Error> {
+        MinecraftMappingsCache::setup_with(location, CacheSettings::default())
+    }
+    pub fn setup_with(location: PathBuf, settings: CacheSettings) -> Result<MinecraftMappingsCache, Error> {
         fs::create_dir_all(&location)?;
         let mcp_cache = location.join("mcp");
         let spigot_cache = location.join("spigot");
+        let mojang_cache = location.join("mojang");
+        let fabric_cache = location.join("fabric");
         fs::create_dir_all(&mcp_cache)?;
         fs::create_dir_all(&spigot_cache)?;
-        let spigot = SpigotMappingsCache::setup(spigot_cache)?;
-        let mcp = McpVersionCache::setup(mcp_cache)?;
-        Ok(MinecraftMappingsCache { spigot, mcp })
+        fs::create_dir_all(&mojang_cache)?;
+        fs::create_dir_all(&fabric_cache)?;
+        let downloader = Downloader::new(settings.offline, settings.transport, settings.mirrors);
+        let spigot = SpigotMappingsCache::setup(spigot_cache, downloader.clone())?;
+        let mojang = MojangMappingsCache::setup(mojang_cache, downloader.clone())?;
+        let fabric = FabricMappingsCache::setup(fabric_cache, downloader.clone())?;
+        let mcp = McpVersionCache::setup(mcp_cache, downloader)?;
+        Ok(MinecraftMappingsCache {
+            location, spigot, mcp, mojang, fabric,
+            mapped: LoadingCache::new(LruCache::new(MAPPED_CACHE_BUDGET))
+        })
+    }
+    /// Access the on-disk contents of the cache
+    #[inline]
+    pub fn directory(&self) -> CacheDirectory {
+        CacheDirectory::new(self.location.clone())
     }
     #[inline]
-    pub fn load_mcp_mappings(&self, mcp: McpVersion) -> Result<Arc<McpMappings>, Error> {
+    pub fn load_mcp_mappings(&self, mcp: McpVersionSpec) -> Result<Arc<McpMappings>, Error> {
         self.mcp.load_mappings(mcp)
     }
+    /// Load the constructors, static methods, exceptions and access transformers
+    /// from the `mcp_config` artifact, which is only published for 1.13 and later.
+    #[inline]
+    pub fn load_mcp_config(&self, version: MinecraftVersion) -> Result<Arc<McpConfig>, Error> {
+        self.mcp.load_config(version)
+    }
     #[inline]
     pub fn load_srg_mappings(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
         self.mcp.load_srg_mappings(version)
     }
+    /// The format MCP publishes the srg data for the specified version in,
+    /// which is also the format we cache it in.
+    #[inline]
+    pub fn srg_format(&self, version: MinecraftVersion) -> SrgFormat {
+        self.mcp.srg_format(version)
+    }
     #[inline]
     pub fn load_spigot_mappings(&self, version: MinecraftVersion) -> Result<Arc<SpigotMappings>, Error> {
         self.spigot.load_mappings(version)
     }
-}
\ No newline at end of file
+    /// Load a specific revision of spigot's mappings, which may be older than the latest for its version
+    #[inline]
+    pub fn load_spigot_revision(&self, revision: &SpigotRevision) -> Result<Arc<SpigotMappings>, Error> {
+        self.spigot.load_revision(revision)
+    }
+    /// Load the versioned package CraftBukkit relocates the server classes into for the spigot revision
+    #[inline]
+    pub fn load_craftbukkit_package(&self, revision: &SpigotRevision) -> Result<String, Error> {
+        self.spigot.load_craftbukkit_package(revision)
+    }
+    /// Load mojang's official mappings, from the obfuscated names into mojang's names
+    #[inline]
+    pub fn load_mojang_mappings(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
+        self.mojang.load_mappings(version)
+    }
+    /// Use local ProGuard files as mojang's mappings for the specified version,
+    /// which is useful for versions that aren't in mojang's version manifest.
+    #[inline]
+    ///
+    /// This forgets any mapped stores we've opened for the version, since they may have used the old mappings.
+    pub fn import_mojang_mappings(&self, version: MinecraftVersion, files: &[PathBuf]) -> Result<(), Error> {
+        self.mojang.import_mappings(version, files)?;
+        self.invalidate_mapped(&self.targets_location(version));
+        Ok(())
+    }
+    /// Load fabric's intermediary mappings, from the obfuscated names into the intermediary names
+    #[inline]
+    pub fn load_intermediary_mappings(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
+        self.fabric.load_intermediary(version)
+    }
+    /// Load a build of yarn, from the intermediary names into the yarn names
+    #[inline]
+    pub fn load_yarn_mappings(&self, version: YarnVersion) -> Result<FrozenMappings, Error> {
+        self.fabric.load_yarn(version)
+    }
+    /// Find the latest build of yarn for the specified minecraft version
+    #[inline]
+    pub fn resolve_yarn_version(&self, version: MinecraftVersion) -> Result<YarnVersion, Error> {
+        self.fabric.latest_yarn_version(version)
+    }
+    /// The usage of the in-memory caches, which evict their least recently used versions
+    pub fn memory_stats(&self) -> MemoryStats {
+        let (srg, mcp) = self.mcp.memory_stats();
+        MemoryStats { srg, mcp, mapped: self.mapped.values().stats() }
+    }
+    /// The directory where computed targets for the specified version are stored
+    #[inline]
+    pub fn targets_location(&self, version: MinecraftVersion) -> PathBuf {
+        self.location.join(format!("targets/{}", version))
+    }
+    /// Load the mapped store at the specified location,
+    /// computing and writing it if it's missing or has a different fingerprint.
+    ///
+    /// The fingerprint identifies the cached inputs of the store,
+    /// and is `None` if some of them haven't been cached yet.
+    /// Computing the store must cache all of its inputs, so we can record their fingerprint.
+    ///
+    /// Stores we've already opened are used without checking their fingerprint again,
+    /// so anything that changes their inputs needs to call `invalidate_mapped`.
+    pub fn load_mapped<P, F>(&self, location: PathBuf, fingerprint: P, compute: F) -> Result<Arc<MappedMappings>, Error>
+        where P: Fn() -> Result<Option<String>, Error>, F: FnOnce() -> Result<FrozenMappings, Error> {
+        // Only one person computes each store, but different stores are computed in parallel
+        self.mapped.load_sized(&location, || {
+            if let Some(ref expected) = fingerprint()? {
+                // If the existing store is corrupted or outdated, we just compute it again
+                if let Ok(mapped) = MappedMappings::open(&location) {
+                    if mapped.fingerprint() == expected.as_str() {
+                        let size = mapped.file_size();
+                        return Ok((Arc::new(mapped), size))
+                    }
+                }
+            }
+            let mappings = compute()?;
+            let fingerprint = fingerprint()?
+                .ok_or_else(|| format_err!("Inputs of {} weren't cached", location.display()))?;
+            fs::create_dir_all(location.parent().unwrap())?;
+            MappedMappings::write(&mappings, &fingerprint, &location)?;
+            let mapped = MappedMappings::open(&location)?;
+            let size = mapped.file_size();
+            Ok((Arc::new(mapped), size))
+        })
+    }
+    /// Forget the mapped stores we've opened inside the specified directory,
+    /// so their fingerprints are checked again the next time they're loaded.
+    pub fn invalidate_mapped(&self, directory: &Path) {
+        self.mapped.update(|mapped| mapped.retain(|location| !location.starts_with(directory)));
+    }
+    /// Ensure the data needed by the specified task is cached on disk, without loading it
+    pub fn fetch(&self, task: WarmTask) -> Result<(), Error> {
+        match task {
+            WarmTask::Srg(version) => {
+                self.mcp.fetch_srg_mappings(version)?;
+                Ok(())
+            },
+            WarmTask::Mcp(version) => self.mcp.fetch_version(version),
+            WarmTask::Spigot(version) => self.spigot.fetch_version(version),
+        }
+    }
+    /// Fetch all the specified tasks in parallel, using the specified number of workers
+    ///
+    /// The progress callback is invoked after each task finishes.
+    pub fn warm<F>(&self, tasks: Vec<WarmTask>, workers: usize, progress: F) -> WarmReport
+        where F: Fn(WarmProgress) + Sync {
+        warm::warm(self, tasks, workers, progress)
+    }
+    /// Check that the MCP version was exported for the minecraft version,
+    /// failing with `IncompatibleMcpVersion` if it wasn't.
+    #[inline]
+    pub fn check_mcp_version(&self, version: McpVersion, minecraft_version: MinecraftVersion) -> Result<(), Error> {
+        self.mcp.check_compatible(version, minecraft_version)
+    }
+    /// Resolve a symbolic MCP version like `snapshot_latest` into an exact version
+    #[inline]
+    pub fn resolve_mcp_version(
+        &self,
+        request: McpVersionRequest,
+        minecraft_version: MinecraftVersion
+    ) -> Result<McpVersionSpec, Error> {
+        Ok(self.mcp.resolve_version(request, minecraft_version)?)
+    }
+    /// All the minecraft versions known to MCP
+    #[inline]
+    pub fn known_minecraft_versions(&self) -> Vec<MinecraftVersion> {
+        self.mcp.known_minecraft_versions()
+    }
+    /// All the MCP versions known to MCPBot
+    #[inline]
+    pub fn known_mcp_versions(&self) -> Vec<McpVersion> {
+        self.mcp.known_versions()
+    }
+    /// All the minecraft versions spigot has published
+    #[inline]
+    pub fn known_spigot_versions(&self) -> Result<Vec<MinecraftVersion>, Error> {
+        self.spigot.known_versions()
+    }
+    /// The revisions of spigot's mappings in BuildData, from newest to oldest
+    #[inline]
+    pub fn known_spigot_revisions(&self, version: Option<MinecraftVersion>) -> Result<Vec<SpigotRevisionInfo>, Error> {
+        self.spigot.known_revisions(version)
+    }
+    /// Find the spigot revisions made for the server jar at the specified location
+    #[inline]
+    pub fn identify_server_jar(&self, location: &Path) -> Result<Vec<SpigotRevisionInfo>, Error> {
+        self.spigot.identify_server_jar(location)
+    }
+    /// Check the server jar at the specified location matches the spigot revision
+    #[inline]
+    pub fn verify_server_jar(&self, revision: &SpigotRevision, location: &Path) -> Result<(), Error> {
+        self.spigot.verify_server_jar(revision, location)
+    }
+}
+
+/// The usage of the in-memory caches of loaded mappings
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct MemoryStats {
+    pub srg: LruStats,
+    pub mcp: LruStats,
+    /// The mapped stores we have open, whose sizes are the sizes of their files
+    pub mapped: LruStats
+}
+
+/// Indicates we needed something that isn't cached while in offline mode
+#[derive(Debug, Fail)]
+#[fail(display = "{} is not cached and we're offline", _0)]
+pub struct NotCachedOffline(pub String);
+/// Indicates the cache has files without a valid manifest while in offline mode
+///
+/// They may have been left incomplete by a killed process, so we can't trust them without downloading them again.
+#[derive(Debug, Fail)]
+#[fail(display = "{} has no valid manifest and we're offline, so we can't download it again", _0)]
+pub struct UnverifiedCacheOffline(pub String);
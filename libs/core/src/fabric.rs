@@ -0,0 +1,398 @@
+//! Fabric's intermediary and yarn mappings, which are published in the Tiny format.
+//!
+//! Intermediary gives every obfuscated name a stable name that's the same across versions,
+//! and yarn maps the intermediary names into the crowd-sourced deobfuscated names.
+//! Unlike intermediary, yarn has many builds for each minecraft version.
+//!
+//! We cache the Tiny files as-is, since they may contain more than two namespaces.
+use std::io::{copy, Cursor};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::str::FromStr;
+use std::fmt::{self, Display, Formatter};
+
+use indexmap::IndexMap;
+use failure::Error;
+use failure_derive::Fail;
+use serde::ser::{Serialize, Serializer};
+use serde::de::{self, Deserialize, Deserializer};
+use srglib::prelude::*;
+use zip::ZipArchive;
+
+use crate::MinecraftVersion;
+use crate::utils::{LoadingCache, Downloader, write_atomic};
+use crate::manifest::CacheManifest;
+use crate::mirrors::Upstream;
+use crate::transport::HttpNotFound;
+
+/// The files we cache for each intermediary version or yarn build
+pub(crate) const TINY_FILES: [&str; 1] = ["mappings.tiny"];
+
+pub(crate) struct FabricMappingsCache {
+    cache_location: PathBuf,
+    intermediary: LoadingCache<IndexMap<MinecraftVersion, FrozenMappings>>,
+    yarn: LoadingCache<IndexMap<YarnVersion, FrozenMappings>>,
+    downloader: Downloader
+}
+impl FabricMappingsCache {
+    pub fn setup(cache_location: PathBuf, downloader: Downloader) -> Result<FabricMappingsCache, Error> {
+        assert!(cache_location.exists());
+        Ok(FabricMappingsCache {
+            cache_location,
+            intermediary: LoadingCache::default(),
+            yarn: LoadingCache::default(),
+            downloader
+        })
+    }
+    /// Load the mappings from the obfuscated names into the intermediary names
+    pub fn load_intermediary(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
+        self.intermediary.load(&version, || {
+            let directory = self.cache_location.join(format!("intermediary/{}", version));
+            let path = format!("net/fabricmc/intermediary/{0}/intermediary-{0}.jar", version);
+            self.fetch_tiny(&directory, &path)?;
+            Ok(load_tiny_file(&directory)?.mappings("official", "intermediary")?)
+        })
+    }
+    /// Load the mappings from the intermediary names into the yarn names
+    pub fn load_yarn(&self, version: YarnVersion) -> Result<FrozenMappings, Error> {
+        self.yarn.load(&version, || {
+            let directory = self.cache_location.join(format!("yarn/{}", version));
+            // Newer builds are also published in Tiny v2, which we prefer since it has the parameters
+            let path = format!("net/fabricmc/yarn/{0}/yarn-{0}-v2.jar", version);
+            match self.fetch_tiny(&directory, &path) {
+                Err(ref e) if e.downcast_ref::<HttpNotFound>().is_some() => {
+                    self.fetch_tiny(&directory, &format!("net/fabricmc/yarn/{0}/yarn-{0}.jar", version))?;
+                },
+                result => result?
+            }
+            Ok(load_tiny_file(&directory)?.mappings("intermediary", "named")?)
+        })
+    }
+    /// Find the latest yarn build for the specified minecraft version
+    pub fn latest_yarn_version(&self, minecraft_version: MinecraftVersion) -> Result<YarnVersion, Error> {
+        let metadata = self.downloader.download_buffer(
+            Upstream::FabricMaven,
+            "net/fabricmc/yarn/maven-metadata.xml"
+        )?;
+        let metadata = String::from_utf8_lossy(&metadata);
+        metadata.split("<version>").skip(1)
+            .filter_map(|part| part.find("</version>").map(|end| &part[..end]))
+            .filter_map(|version| version.parse::<YarnVersion>().ok())
+            .filter(|version| version.minecraft_version == minecraft_version)
+            .max()
+            .ok_or_else(|| MissingYarnVersion(minecraft_version).into())
+    }
+    /// Ensure the Tiny file from the specified jar on the fabric maven is cached
+    fn fetch_tiny(&self, directory: &Path, path: &str) -> Result<(), Error> {
+        if CacheManifest::check_valid(directory, &TINY_FILES, &self.downloader)? {
+            return Ok(())
+        }
+        let buffer = self.downloader.download_buffer(Upstream::FabricMaven, path)?;
+        fs::create_dir_all(directory)?;
+        let mut manifest = CacheManifest::new();
+        manifest.record_source(path, &buffer);
+        let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
+        let mut entry = archive.by_name("mappings/mappings.tiny")?;
+        write_atomic(&directory.join(TINY_FILES[0]), |writer| {
+            copy(&mut entry, writer)?;
+            Ok(())
+        })?;
+        manifest.record_file(directory, TINY_FILES[0])?;
+        manifest.save(directory)?;
+        Ok(())
+    }
+}
+fn load_tiny_file(directory: &Path) -> Result<TinyMappings, Error> {
+    Ok(TinyMappings::parse(&fs::read_to_string(directory.join(TINY_FILES[0]))?)?)
+}
+
+/// A build of yarn, like `1.14.4+build.18`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct YarnVersion {
+    pub minecraft_version: MinecraftVersion,
+    pub build: u32
+}
+impl FromStr for YarnVersion {
+    type Err = InvalidYarnVersion;
+
+    fn from_str(s: &str) -> Result<YarnVersion, InvalidYarnVersion> {
+        let error = || InvalidYarnVersion(s.into());
+        let separator = s.find("+build.").ok_or_else(error)?;
+        Ok(YarnVersion {
+            minecraft_version: s[..separator].parse().map_err(|_| error())?,
+            build: s[(separator + 7)..].parse().map_err(|_| error())?
+        })
+    }
+}
+impl Display for YarnVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}+build.{}", self.minecraft_version, self.build)
+    }
+}
+impl Serialize for YarnVersion {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+        S: Serializer {
+        serializer.serialize_str(&format!("{}", self))
+    }
+}
+impl<'de> Deserialize<'de> for YarnVersion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+        D: Deserializer<'de> {
+        struct YarnVersionVisitor;
+        impl<'de> ::serde::de::Visitor<'de> for YarnVersionVisitor {
+            type Value = YarnVersion;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a YarnVersion")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<YarnVersion, E> where
+                E: de::Error, {
+                YarnVersion::from_str(v).map_err(de::Error::custom)
+            }
+        }
+        deserializer.deserialize_str(YarnVersionVisitor)
+    }
+}
+
+/// The contents of a Tiny file, which names everything in multiple namespaces
+///
+/// Both Tiny v1 and v2 are supported.
+/// The descriptors of members are always given in the first namespace,
+/// and names that are missing from a namespace are left empty.
+#[derive(Clone, Debug, Default)]
+pub struct TinyMappings {
+    pub namespaces: Vec<String>,
+    /// The names of each class, in the same order as the namespaces
+    pub classes: Vec<Vec<String>>,
+    pub fields: Vec<TinyMember>,
+    pub methods: Vec<TinyMember>
+}
+#[derive(Clone, Debug)]
+pub struct TinyMember {
+    /// The declaring class, in the first namespace
+    pub class: String,
+    /// The descriptor, in the first namespace
+    pub descriptor: String,
+    pub names: Vec<String>
+}
+impl TinyMappings {
+    pub fn parse(text: &str) -> Result<TinyMappings, InvalidTinyMappings> {
+        let mut lines = text.lines();
+        let header = lines.next().ok_or_else(|| InvalidTinyMappings("Missing header".into()))?;
+        let header: Vec<&str> = header.split('\t').collect();
+        match header[0] {
+            "v1" => TinyMappings::parse_v1(&header[1..], lines),
+            "tiny" if header.get(1) == Some(&"2") => {
+                TinyMappings::parse_v2(header.get(3..).unwrap_or(&[]), lines)
+            },
+            _ => Err(InvalidTinyMappings(format!("Unsupported header {:?}", header.join("\t"))))
+        }
+    }
+    fn parse_v1<'a, I>(namespaces: &[&str], lines: I) -> Result<TinyMappings, InvalidTinyMappings>
+        where I: Iterator<Item=&'a str> {
+        let mut result = TinyMappings::with_namespaces(namespaces);
+        let count = namespaces.len();
+        for line in lines {
+            if line.is_empty() || line.starts_with('#') { continue }
+            let error = || InvalidTinyMappings(line.into());
+            let parts: Vec<&str> = line.split('\t').collect();
+            match parts[0] {
+                "CLASS" if parts.len() == count + 1 => {
+                    result.classes.push(parse_names(&parts[1..]));
+                },
+                "FIELD" | "METHOD" if parts.len() == count + 3 => {
+                    let member = TinyMember {
+                        class: parts[1].into(),
+                        descriptor: parts[2].into(),
+                        names: parse_names(&parts[3..])
+                    };
+                    if parts[0] == "FIELD" {
+                        result.fields.push(member);
+                    } else {
+                        result.methods.push(member);
+                    }
+                },
+                _ => return Err(error())
+            }
+        }
+        Ok(result)
+    }
+    fn parse_v2<'a, I>(namespaces: &[&str], lines: I) -> Result<TinyMappings, InvalidTinyMappings>
+        where I: Iterator<Item=&'a str> {
+        let mut result = TinyMappings::with_namespaces(namespaces);
+        let count = namespaces.len();
+        let mut current_class: Option<String> = None;
+        for line in lines {
+            if line.is_empty() { continue }
+            let error = || InvalidTinyMappings(line.into());
+            let depth = line.len() - line.trim_start_matches('\t').len();
+            let parts: Vec<&str> = line[depth..].split('\t').collect();
+            match (depth, parts[0]) {
+                (0, "c") if parts.len() == count + 1 => {
+                    let names = parse_names(&parts[1..]);
+                    current_class = Some(names[0].clone());
+                    result.classes.push(names);
+                },
+                (1, "f") | (1, "m") if parts.len() == count + 2 => {
+                    let class = current_class.clone().ok_or_else(error)?;
+                    let member = TinyMember {
+                        class,
+                        descriptor: parts[1].into(),
+                        names: parse_names(&parts[2..])
+                    };
+                    if parts[0] == "f" {
+                        result.fields.push(member);
+                    } else {
+                        result.methods.push(member);
+                    }
+                },
+                // Skip the properties, comments, parameters and local variables
+                (0, "c") | (1, "f") | (1, "m") => return Err(error()),
+                _ if depth > 0 => {},
+                _ => return Err(error())
+            }
+        }
+        Ok(result)
+    }
+    fn with_namespaces(namespaces: &[&str]) -> TinyMappings {
+        TinyMappings {
+            namespaces: namespaces.iter().map(|s| String::from(*s)).collect(),
+            ..TinyMappings::default()
+        }
+    }
+    fn namespace_index(&self, namespace: &str) -> Result<usize, InvalidTinyMappings> {
+        self.namespaces.iter().position(|s| s == namespace)
+            .ok_or_else(|| InvalidTinyMappings(format!("Missing namespace {}", namespace)))
+    }
+    /// Extract the mappings from one namespace into another
+    ///
+    /// Anything that's missing a name in either namespace is left unmapped.
+    pub fn mappings(&self, original: &str, renamed: &str) -> Result<FrozenMappings, InvalidTinyMappings> {
+        let original = self.namespace_index(original)?;
+        let renamed = self.namespace_index(renamed)?;
+        let mut builder = SimpleMappings::default();
+        // The members are declared in the first namespace, so we need to translate them
+        let mut primary_classes = IndexMap::with_capacity(self.classes.len());
+        for names in &self.classes {
+            let original_name = match tiny_name(names, original) {
+                Some(original_name) => original_name,
+                None => continue
+            };
+            primary_classes.insert(names[0].as_str(), original_name);
+            match tiny_name(names, renamed) {
+                Some(renamed_name) if renamed_name != original_name => {
+                    builder.set_remapped_class(
+                        ReferenceType::from_internal_name(original_name),
+                        ReferenceType::from_internal_name(renamed_name)
+                    );
+                },
+                _ => {}
+            }
+        }
+        let primary2original = |class: &str| -> String {
+            primary_classes.get(class).map_or_else(|| class.into(), |&name| name.into())
+        };
+        for field in &self.fields {
+            let (original_name, renamed_name) = match (tiny_name(&field.names, original), tiny_name(&field.names, renamed)) {
+                (Some(original_name), Some(renamed_name)) => (original_name, renamed_name),
+                _ => continue
+            };
+            if original_name == renamed_name { continue }
+            builder.set_field_name(
+                FieldData::new(
+                    original_name.into(),
+                    ReferenceType::from_internal_name(&primary2original(&field.class))
+                ),
+                renamed_name.into()
+            );
+        }
+        for method in &self.methods {
+            let (original_name, renamed_name) = match (tiny_name(&method.names, original), tiny_name(&method.names, renamed)) {
+                (Some(original_name), Some(renamed_name)) => (original_name, renamed_name),
+                _ => continue
+            };
+            if original_name == renamed_name { continue }
+            let descriptor = remap_descriptor_classes(&method.descriptor, &primary2original);
+            builder.set_method_name(
+                MethodData::new(
+                    original_name.into(),
+                    ReferenceType::from_internal_name(&primary2original(&method.class)),
+                    MethodSignature::from_descriptor(&descriptor)
+                ),
+                renamed_name.into()
+            );
+        }
+        Ok(builder.frozen())
+    }
+}
+#[inline]
+fn parse_names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| String::from(*name)).collect()
+}
+/// The name in the specified namespace, unless it's missing
+#[inline]
+fn tiny_name(names: &[String], namespace: usize) -> Option<&str> {
+    Some(names[namespace].as_str()).filter(|name| !name.is_empty())
+}
+fn remap_descriptor_classes<F: Fn(&str) -> String>(descriptor: &str, remap: F) -> String {
+    let mut result = String::with_capacity(descriptor.len());
+    let mut remaining = descriptor;
+    while let Some(start) = remaining.find('L') {
+        let end = match remaining[start..].find(';') {
+            Some(end) => start + end,
+            None => break
+        };
+        result.push_str(&remaining[..(start + 1)]);
+        result.push_str(&remap(&remaining[(start + 1)..end]));
+        remaining = &remaining[end..];
+    }
+    result.push_str(remaining);
+    result
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid yarn version {:?}", _0)]
+pub struct InvalidYarnVersion(String);
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid tiny mappings: {}", _0)]
+pub struct InvalidTinyMappings(String);
+#[derive(Debug, Fail)]
+#[fail(display = "Yarn doesn't have any builds for minecraft {}", _0)]
+pub struct MissingYarnVersion(pub MinecraftVersion);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn parse_tiny() {
+        let v1 = TinyMappings::parse(
+            "v1\tofficial\tintermediary\tnamed\n\
+            CLASS\ta\tnet/minecraft/class_1\tnet/minecraft/World\n\
+            FIELD\ta\tLa;\tb\tfield_1\tparent\n\
+            METHOD\ta\t(La;)V\tc\tmethod_1\t\n"
+        ).unwrap();
+        let v2 = TinyMappings::parse(
+            "tiny\t2\t0\tofficial\tintermediary\tnamed\n\
+            \tsorted-by-namespace\n\
+            c\ta\tnet/minecraft/class_1\tnet/minecraft/World\n\
+            \tf\tLa;\tb\tfield_1\tparent\n\
+            \tm\t(La;)V\tc\tmethod_1\t\n\
+            \t\tp\t1\t\t\tother\n"
+        ).unwrap();
+        for tiny in &[v1, v2] {
+            assert_eq!(tiny.classes, vec![vec!["a", "net/minecraft/class_1", "net/minecraft/World"]]);
+            assert_eq!(tiny.fields[0].names, vec!["b", "field_1", "parent"]);
+            assert_eq!(tiny.methods[0].names, vec!["c", "method_1", ""]);
+            // The method isn't named, so it shouldn't be mapped back to its obfuscated name
+            assert_eq!(tiny.mappings("official", "named").unwrap().methods().count(), 0);
+            assert_eq!(tiny.mappings("intermediary", "named").unwrap().methods().count(), 0);
+            assert_eq!(tiny.mappings("official", "intermediary").unwrap().methods().count(), 1);
+        }
+        assert_eq!(
+            remap_descriptor_classes("(La;[La;I)La;", |_| "net/minecraft/class_1".into()),
+            "(Lnet/minecraft/class_1;[Lnet/minecraft/class_1;I)Lnet/minecraft/class_1;"
+        );
+    }
+}
@@ -1,8 +1,21 @@
 pub mod mcp;
+pub mod mcp_config;
+pub mod access;
 pub mod spigot;
+pub mod mojang;
+pub mod fabric;
 pub mod cache;
 pub mod version;
+pub mod transport;
+pub mod mirrors;
+pub mod maintenance;
+pub mod warm;
+pub mod mapped;
 mod utils;
+mod manifest;
+mod snapshot;
 
 pub use self::version::MinecraftVersion;
-pub use self::mcp::{McpVersion, McpVersionSpec};
+pub use self::mcp::{McpVersion, McpVersionSpec, McpVersionRequest};
+pub use self::spigot::SpigotRevision;
+pub use self::fabric::YarnVersion;
@@ -0,0 +1,347 @@
+//! Inspects and cleans up the on-disk cache.
+//!
+//! Unlike `MinecraftMappingsCache`, this never needs network access,
+//! so it can be used to manage a cache without setting anything up.
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{SystemTime, Duration};
+use std::fmt::{self, Display, Formatter};
+
+use failure::Error;
+
+use crate::MinecraftVersion;
+use crate::mcp::{McpVersionSpec, McpVersionList, MCP_FILES, srg_files};
+use crate::spigot::{MAPPING_FILES, cached_minecraft_version};
+use crate::mojang::MOJANG_FILES;
+use crate::fabric::{YarnVersion, TINY_FILES};
+use crate::manifest::CacheManifest;
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CacheEntryKind {
+    /// The srg mappings for a minecraft version
+    Srg(MinecraftVersion),
+    /// The MCP names for a snapshot or stable version
+    Mcp(McpVersionSpec),
+    /// The spigot mappings extracted from a BuildData commit,
+    /// along with their minecraft version if it could be determined
+    Spigot(String, Option<MinecraftVersion>),
+    /// The spigot mappings for a minecraft version,
+    /// left over from before they were cached by BuildData commit
+    LegacySpigot(MinecraftVersion),
+    /// Mojang's official mappings for a minecraft version
+    Mojang(MinecraftVersion),
+    /// Fabric's intermediary mappings for a minecraft version
+    Intermediary(MinecraftVersion),
+    /// A build of fabric's yarn mappings
+    Yarn(YarnVersion),
+    /// The clone of the BuildData repository, which is shared by all spigot versions
+    BuildData,
+    /// The mapped stores of the targets we've computed for a minecraft version
+    Targets(MinecraftVersion),
+}
+impl CacheEntryKind {
+    /// The files that must be present for this entry to be valid,
+    /// or `None` if we don't track it in a manifest.
+    fn expected_files(&self) -> Option<&'static [&'static str]> {
+        match *self {
+            CacheEntryKind::Srg(version) => Some(srg_files(version)),
+            CacheEntryKind::Mcp(_) => Some(&MCP_FILES),
+            CacheEntryKind::Spigot(..) => Some(&MAPPING_FILES),
+            CacheEntryKind::Mojang(_) => Some(&MOJANG_FILES),
+            CacheEntryKind::Intermediary(_) | CacheEntryKind::Yarn(_) => Some(&TINY_FILES),
+            CacheEntryKind::LegacySpigot(_) | CacheEntryKind::BuildData |
+            CacheEntryKind::Targets(_) => None,
+        }
+    }
+    /// The directory of this entry, relative to the root of the cache
+    fn relative_location(&self) -> String {
+        match *self {
+            CacheEntryKind::Srg(version) => format!("mcp/versions/{}", version),
+            CacheEntryKind::Mcp(spec) => format!("mcp/{}", spec),
+            CacheEntryKind::Spigot(ref commit, _) => format!("spigot/revisions/{}", commit),
+            CacheEntryKind::LegacySpigot(version) => format!("spigot/versions/{}", version),
+            CacheEntryKind::Mojang(version) => format!("mojang/versions/{}", version),
+            CacheEntryKind::Intermediary(version) => format!("fabric/intermediary/{}", version),
+            CacheEntryKind::Yarn(version) => format!("fabric/yarn/{}", version),
+            CacheEntryKind::BuildData => "spigot/BuildData".into(),
+            CacheEntryKind::Targets(version) => format!("targets/{}", version),
+        }
+    }
+    /// The minecraft version this entry belongs to, if any
+    ///
+    /// MCP versions can be exported for several minecraft versions,
+    /// so they need the version list to be resolved (see `CacheEntry::minecraft_versions`).
+    pub fn minecraft_version(&self) -> Option<MinecraftVersion> {
+        match *self {
+            CacheEntryKind::Srg(version) |
+            CacheEntryKind::LegacySpigot(version) |
+            CacheEntryKind::Mojang(version) |
+            CacheEntryKind::Intermediary(version) |
+            CacheEntryKind::Targets(version) => Some(version),
+            CacheEntryKind::Yarn(version) => Some(version.minecraft_version),
+            CacheEntryKind::Spigot(_, version) => version,
+            CacheEntryKind::Mcp(_) | CacheEntryKind::BuildData => None,
+        }
+    }
+    /// If it's safe to delete this entry
+    ///
+    /// We never prune BuildData, since it's needed for every spigot version
+    /// and it's much more expensive to clone it again than to keep it around.
+    #[inline]
+    pub fn is_prunable(&self) -> bool {
+        *self != CacheEntryKind::BuildData
+    }
+}
+impl Display for CacheEntryKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            CacheEntryKind::Srg(version) => write!(f, "srg {}", version),
+            CacheEntryKind::Mcp(spec) => write!(f, "mcp {}", spec),
+            CacheEntryKind::Spigot(ref commit, Some(version)) => {
+                write!(f, "spigot {} ({})", short_commit(commit), version)
+            },
+            CacheEntryKind::Spigot(ref commit, None) => write!(f, "spigot {}", short_commit(commit)),
+            CacheEntryKind::LegacySpigot(version) => write!(f, "spigot {} (legacy)", version),
+            CacheEntryKind::Mojang(version) => write!(f, "mojang {}", version),
+            CacheEntryKind::Intermediary(version) => write!(f, "intermediary {}", version),
+            CacheEntryKind::Yarn(version) => write!(f, "yarn {}", version),
+            CacheEntryKind::BuildData => f.write_str("BuildData"),
+            CacheEntryKind::Targets(version) => write!(f, "targets {}", version),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub kind: CacheEntryKind,
+    pub location: PathBuf,
+    /// The minecraft versions this entry belongs to, which is empty if it's shared by all of them
+    pub minecraft_versions: Vec<MinecraftVersion>,
+    /// The total size of the entry in bytes
+    pub size: u64,
+    /// The last time the entry was written
+    pub modified: SystemTime
+}
+impl CacheEntry {
+    fn load(kind: CacheEntryKind, location: PathBuf) -> Result<CacheEntry, Error> {
+        let size = directory_size(&location)?;
+        // The manifest is written last, so it's the best indication of when the entry was complete
+        let manifest = location.join("manifest.json");
+        let modified = if manifest.exists() {
+            fs::metadata(&manifest)?.modified()?
+        } else {
+            fs::metadata(&location)?.modified()?
+        };
+        let minecraft_versions = kind.minecraft_version().into_iter().collect();
+        Ok(CacheEntry { kind, location, minecraft_versions, size, modified })
+    }
+    /// How long ago the entry was written
+    #[inline]
+    pub fn age(&self) -> Duration {
+        self.modified.elapsed().unwrap_or_else(|_| Duration::from_secs(0))
+    }
+    /// Check that the entry hasn't been corrupted
+    pub fn verify(&self) -> bool {
+        match self.kind.expected_files() {
+            Some(files) => CacheManifest::is_verified(&self.location, files),
+            None => true
+        }
+    }
+}
+
+/// Decides which entries should be removed from the cache
+///
+/// Entries are removed if they match any of the criteria.
+#[derive(Clone, Debug, Default)]
+pub struct PrunePolicy {
+    /// Remove entries that were written longer ago than this
+    pub max_age: Option<Duration>,
+    /// Remove all the entries belonging to these minecraft versions
+    pub versions: Vec<MinecraftVersion>,
+    /// Remove the oldest entries until the cache fits within this many bytes
+    pub max_size: Option<u64>,
+    /// Remove entries that fail verification
+    pub invalid: bool
+}
+impl PrunePolicy {
+    fn matches(&self, entry: &CacheEntry) -> bool {
+        if let Some(max_age) = self.max_age {
+            if entry.age() > max_age { return true }
+        }
+        if entry.minecraft_versions.iter().any(|version| self.versions.contains(version)) {
+            return true
+        }
+        self.invalid && !entry.verify()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PruneReport {
+    pub removed: Vec<CacheEntry>,
+    /// The number of bytes we freed
+    pub freed: u64,
+    /// The number of bytes still used by the cache
+    pub remaining: u64
+}
+
+/// The on-disk layout of a `MinecraftMappingsCache`
+#[derive(Clone, Debug)]
+pub struct CacheDirectory {
+    location: PathBuf
+}
+impl CacheDirectory {
+    #[inline]
+    pub fn new(location: PathBuf) -> CacheDirectory {
+        CacheDirectory { location }
+    }
+    #[inline]
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+    /// List all the entries in the cache, ordered from oldest to newest
+    pub fn list_entries(&self) -> Result<Vec<CacheEntry>, Error> {
+        let mut entries = Vec::new();
+        let mcp = self.location.join("mcp");
+        // The version list is only used to tell which minecraft versions the exports belong to,
+        // so it's fine if it hasn't been downloaded yet
+        let mcp_versions = McpVersionList::load(&mcp.join("versions.json")).ok();
+        for (name, location) in list_directory(&mcp.join("versions"))? {
+            if let Ok(version) = name.parse::<MinecraftVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::Srg(version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&mcp)? {
+            if let Ok(spec) = name.parse::<McpVersionSpec>() {
+                let mut entry = CacheEntry::load(CacheEntryKind::Mcp(spec), location)?;
+                if let Some(ref mcp_versions) = mcp_versions {
+                    entry.minecraft_versions = mcp_versions.minecraft_versions_for(spec.version);
+                }
+                entries.push(entry);
+            }
+        }
+        let spigot = self.location.join("spigot");
+        for (name, location) in list_directory(&spigot.join("revisions"))? {
+            if name.len() == 40 {
+                let version = cached_minecraft_version(&location);
+                entries.push(CacheEntry::load(CacheEntryKind::Spigot(name, version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&spigot.join("versions"))? {
+            if let Ok(version) = name.parse::<MinecraftVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::LegacySpigot(version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&self.location.join("mojang/versions"))? {
+            if let Ok(version) = name.parse::<MinecraftVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::Mojang(version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&self.location.join("fabric/intermediary"))? {
+            if let Ok(version) = name.parse::<MinecraftVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::Intermediary(version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&self.location.join("fabric/yarn"))? {
+            if let Ok(version) = name.parse::<YarnVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::Yarn(version), location)?);
+            }
+        }
+        for (name, location) in list_directory(&self.location.join("targets"))? {
+            if let Ok(version) = name.parse::<MinecraftVersion>() {
+                entries.push(CacheEntry::load(CacheEntryKind::Targets(version), location)?);
+            }
+        }
+        let build_data = spigot.join("BuildData");
+        if build_data.is_dir() {
+            entries.push(CacheEntry::load(CacheEntryKind::BuildData, build_data)?);
+        }
+        entries.sort_by_key(|entry| entry.modified);
+        Ok(entries)
+    }
+    /// Identify the current contents of the specified entry by the sha1s recorded in its manifest
+    ///
+    /// Returns `None` if the entry isn't tracked by a manifest, or hasn't been cached yet.
+    pub fn fingerprint(&self, kind: &CacheEntryKind) -> Option<String> {
+        let files = kind.expected_files()?;
+        let manifest = CacheManifest::load(&self.location.join(kind.relative_location()))?;
+        let mut result = format!("{}", kind);
+        for &name in files {
+            result.push_str(&format!(" {}={}", name, manifest.files.get(name)?.sha1));
+        }
+        Some(result)
+    }
+    /// Verify all the entries in the cache, returning the ones that are corrupted
+    pub fn verify(&self) -> Result<Vec<CacheEntry>, Error> {
+        Ok(self.list_entries()?.into_iter()
+            .filter(|entry| !entry.verify())
+            .collect())
+    }
+    /// Remove the entries selected by the specified policy
+    ///
+    /// Any removed entries are downloaded again the next time they're needed.
+    pub fn prune(&self, policy: &PrunePolicy) -> Result<PruneReport, Error> {
+        let entries = self.list_entries()?;
+        let mut report = PruneReport::default();
+        report.remaining = entries.iter().map(|entry| entry.size).sum();
+        let mut remaining_entries = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if entry.kind.is_prunable() && policy.matches(&entry) {
+                report.remove(entry)?;
+            } else {
+                remaining_entries.push(entry);
+            }
+        }
+        if let Some(max_size) = policy.max_size {
+            // Since the entries are sorted by age, this removes the oldest ones first
+            for entry in remaining_entries {
+                if report.remaining <= max_size { break }
+                if entry.kind.is_prunable() {
+                    report.remove(entry)?;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+impl PruneReport {
+    fn remove(&mut self, entry: CacheEntry) -> Result<(), Error> {
+        fs::remove_dir_all(&entry.location)?;
+        self.freed += entry.size;
+        self.remaining -= entry.size;
+        self.removed.push(entry);
+        Ok(())
+    }
+}
+
+/// Abbreviate a BuildData commit, without assuming it's a full-length hash
+#[inline]
+fn short_commit(commit: &str) -> &str {
+    commit.get(..10).unwrap_or(commit)
+}
+
+fn list_directory(location: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let mut result = Vec::new();
+    if !location.is_dir() { return Ok(result) }
+    for entry in fs::read_dir(location)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() { continue }
+        if let Ok(name) = entry.file_name().into_string() {
+            result.push((name, entry.path()));
+        }
+    }
+    Ok(result)
+}
+
+fn directory_size(location: &Path) -> Result<u64, Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(location)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += directory_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,143 @@
+//! Records the artifacts we've cached for each version, so we can detect corrupted caches.
+//!
+//! The manifest is always written after the files it describes,
+//! so if we're killed halfway through writing the cache, verification fails and we download it again.
+//! Loading the cache only checks the sizes of the files, since hashing them each time would be too slow,
+//! while `cache verify` checks their full hashes.
+use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+
+use failure::Error;
+use indexmap::IndexMap;
+use serde_derive::{Deserialize, Serialize};
+use sha1::Sha1;
+
+use crate::utils::{write_atomic, Downloader};
+use crate::cache::UnverifiedCacheOffline;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub size: u64,
+    pub sha1: String
+}
+impl ArtifactInfo {
+    pub fn compute(data: &[u8]) -> ArtifactInfo {
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        ArtifactInfo { size: data.len() as u64, sha1: hasher.digest().to_string() }
+    }
+    pub fn of_file(location: &Path) -> Result<ArtifactInfo, Error> {
+        let mut input = BufReader::new(File::open(location)?);
+        let mut hasher = Sha1::new();
+        let mut buffer = [0u8; 8192];
+        let mut size = 0u64;
+        loop {
+            let amount = input.read(&mut buffer)?;
+            if amount == 0 { break }
+            hasher.update(&buffer[..amount]);
+            size += amount as u64;
+        }
+        Ok(ArtifactInfo { size, sha1: hasher.digest().to_string() })
+    }
+}
+
+/// The manifest of a single cached version directory
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CacheManifest {
+    /// The source artifacts we downloaded, keyed by their upstream location
+    #[serde(default)]
+    pub sources: IndexMap<String, ArtifactInfo>,
+    /// The files we've written, keyed by their name inside the directory
+    #[serde(default)]
+    pub files: IndexMap<String, ArtifactInfo>
+}
+impl CacheManifest {
+    #[inline]
+    pub fn new() -> CacheManifest {
+        CacheManifest::default()
+    }
+    /// Load the manifest of the specified directory,
+    /// returning `None` if it's missing or unreadable.
+    pub fn load(directory: &Path) -> Option<CacheManifest> {
+        let file = File::open(directory.join(MANIFEST_FILE)).ok()?;
+        ::serde_json::from_reader(BufReader::new(file)).ok()
+    }
+    /// Check that the directory contains all the expected files,
+    /// and that they exactly match what we originally wrote.
+    pub fn verify(&self, directory: &Path, expected_files: &[&str]) -> bool {
+        expected_files.iter().all(|&name| {
+            match self.files.get(name) {
+                Some(expected) => {
+                    let location = directory.join(name);
+                    match ArtifactInfo::of_file(&location) {
+                        Ok(actual) => actual == *expected,
+                        Err(_) => false
+                    }
+                },
+                None => false
+            }
+        })
+    }
+    /// Check that the directory contains all the expected files,
+    /// and that they have the same sizes as what we originally wrote.
+    pub fn check_sizes(&self, directory: &Path, expected_files: &[&str]) -> bool {
+        expected_files.iter().all(|&name| {
+            match self.files.get(name) {
+                Some(expected) => {
+                    fs::metadata(directory.join(name))
+                        .map_or(false, |metadata| metadata.len() == expected.size)
+                },
+                None => false
+            }
+        })
+    }
+    /// Check if the specified directory has all the expected files, with the sizes given by its manifest
+    ///
+    /// A missing or unreadable manifest is always invalid,
+    /// since we can't tell if the files were completely written.
+    pub fn is_valid(directory: &Path, expected_files: &[&str]) -> bool {
+        CacheManifest::load(directory)
+            .map_or(false, |manifest| manifest.check_sizes(directory, expected_files))
+    }
+    /// Check if the specified directory is valid, before we download it again
+    ///
+    /// In offline mode we can't download it again, so files without a manifest
+    /// fail with `UnverifiedCacheOffline` instead of being trusted.
+    pub fn check_valid(directory: &Path, expected_files: &[&str], downloader: &Downloader) -> Result<bool, UnverifiedCacheOffline> {
+        if CacheManifest::is_valid(directory, expected_files) {
+            return Ok(true)
+        }
+        if downloader.is_offline() && CacheManifest::load(directory).is_none()
+            && expected_files.iter().any(|name| directory.join(name).exists()) {
+            return Err(UnverifiedCacheOffline(directory.display().to_string()))
+        }
+        Ok(false)
+    }
+    /// Check if the specified directory has a valid manifest,
+    /// and that all the expected files exactly match it.
+    ///
+    /// Unlike `is_valid`, this hashes all the files.
+    #[inline]
+    pub fn is_verified(directory: &Path, expected_files: &[&str]) -> bool {
+        CacheManifest::load(directory)
+            .map_or(false, |manifest| manifest.verify(directory, expected_files))
+    }
+    #[inline]
+    pub fn record_source(&mut self, location: &str, data: &[u8]) {
+        self.sources.insert(location.into(), ArtifactInfo::compute(data));
+    }
+    pub fn record_file(&mut self, directory: &Path, name: &str) -> Result<(), Error> {
+        let info = ArtifactInfo::of_file(&directory.join(name))?;
+        self.files.insert(name.into(), info);
+        Ok(())
+    }
+    pub fn save(&self, directory: &Path) -> Result<(), Error> {
+        write_atomic(&directory.join(MANIFEST_FILE), |writer| {
+            ::serde_json::to_writer_pretty(writer, self)?;
+            Ok(())
+        })
+    }
+}
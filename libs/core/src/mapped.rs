@@ -0,0 +1,501 @@
+//! A read-only, memory-mapped mappings store.
+//!
+//! Unlike `FrozenMappings`, this never materializes the mappings on the heap.
+//! All the strings are stored in a single table, and each kind of entry is stored
+//! as a sorted array of fixed-size records that index into the table.
+//! Lookups are binary searches over the mapped file,
+//! so the operating system is free to page the data in and out as needed.
+//!
+//! The layout is as follows (all integers are little-endian `u32`):
+//! - The header: magic, format version, and the number of strings, classes, fields and methods
+//! - The fingerprint of the data the store was computed from
+//! - The string offsets, with one extra offset marking the end of the string data
+//! - The string data
+//! - The class records `(original, renamed)`, sorted by the original name
+//! - The field records `(class, name, renamed)`, sorted by class and name
+//! - The method records `(class, name, descriptor, renamed)`, sorted by class, name and descriptor
+use std::path::Path;
+use std::fs::File;
+use std::io::Write;
+use std::cmp::Ordering;
+use std::str;
+
+use failure::Error;
+use failure_derive::Fail;
+use indexmap::IndexSet;
+use memmap::Mmap;
+use srglib::prelude::*;
+
+use crate::utils::write_atomic;
+
+const MAGIC: &[u8; 8] = b"MMMAPPED";
+/// The current version of the store format, which must be incremented on any change
+const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8 + 4 * 5;
+const CLASS_RECORD: usize = 2;
+const FIELD_RECORD: usize = 3;
+const METHOD_RECORD: usize = 4;
+
+pub struct MappedMappings {
+    map: Mmap,
+    fingerprint: (usize, usize),
+    num_strings: usize,
+    string_offsets: usize,
+    string_data: usize,
+    num_classes: usize,
+    classes: usize,
+    num_fields: usize,
+    fields: usize,
+    num_methods: usize,
+    methods: usize,
+}
+impl MappedMappings {
+    /// Write the specified mappings to a store at the specified location
+    pub fn write(mappings: &FrozenMappings, fingerprint: &str, location: &Path) -> Result<(), Error> {
+        let mut strings = IndexSet::new();
+        let mut classes = Vec::new();
+        for (original, renamed) in mappings.classes() {
+            classes.push([
+                intern(&mut strings, &*original.internal_name()),
+                intern(&mut strings, &*renamed.internal_name())
+            ]);
+        }
+        let mut fields = Vec::new();
+        for (original, renamed) in mappings.fields() {
+            fields.push([
+                intern(&mut strings, &*original.declaring_type().internal_name()),
+                intern(&mut strings, &original.name),
+                intern(&mut strings, &renamed.name)
+            ]);
+        }
+        let mut methods = Vec::new();
+        for (original, renamed) in mappings.methods() {
+            methods.push([
+                intern(&mut strings, &*original.declaring_type().internal_name()),
+                intern(&mut strings, &original.name),
+                intern(&mut strings, &*original.signature().descriptor()),
+                intern(&mut strings, &renamed.name)
+            ]);
+        }
+        {
+            let lookup = |index: &u32| strings.get_index(*index as usize).unwrap().as_str();
+            classes.sort_by(|first, second| lookup(&first[0]).cmp(lookup(&second[0])));
+            fields.sort_by(|first, second| {
+                (lookup(&first[0]), lookup(&first[1])).cmp(&(lookup(&second[0]), lookup(&second[1])))
+            });
+            methods.sort_by(|first, second| {
+                (lookup(&first[0]), lookup(&first[1]), lookup(&first[2]))
+                    .cmp(&(lookup(&second[0]), lookup(&second[1]), lookup(&second[2])))
+            });
+        }
+        let mut output = Vec::with_capacity(256 * 1024);
+        output.extend_from_slice(MAGIC);
+        write_u32(&mut output, FORMAT_VERSION);
+        write_u32(&mut output, strings.len() as u32);
+        write_u32(&mut output, classes.len() as u32);
+        write_u32(&mut output, fields.len() as u32);
+        write_u32(&mut output, methods.len() as u32);
+        write_u32(&mut output, fingerprint.len() as u32);
+        output.extend_from_slice(fingerprint.as_bytes());
+        let mut offset = 0u32;
+        for s in &strings {
+            write_u32(&mut output, offset);
+            offset += s.len() as u32;
+        }
+        write_u32(&mut output, offset);
+        for s in &strings {
+            output.extend_from_slice(s.as_bytes());
+        }
+        for record in &classes {
+            for &index in record { write_u32(&mut output, index) }
+        }
+        for record in &fields {
+            for &index in record { write_u32(&mut output, index) }
+        }
+        for record in &methods {
+            for &index in record { write_u32(&mut output, index) }
+        }
+        write_atomic(location, |writer| {
+            writer.write_all(&output)?;
+            Ok(())
+        })
+    }
+    /// Open the store at the specified location, validating its structure
+    pub fn open(location: &Path) -> Result<MappedMappings, Error> {
+        let file = File::open(location)?;
+        // NOTE: This is only unsafe if the file is modified while we have it mapped.
+        // We always replace the store atomically, so the mapped file is never modified.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_SIZE + 4 || &map[..MAGIC.len()] != MAGIC {
+            return Err(InvalidMappedStore("Invalid magic").into())
+        }
+        let header = |index: usize| read_u32(&map, MAGIC.len() + index * 4) as usize;
+        if header(0) != FORMAT_VERSION as usize {
+            return Err(InvalidMappedStore("Unsupported format version").into())
+        }
+        let (num_strings, num_classes, num_fields, num_methods) =
+            (header(1), header(2), header(3), header(4));
+        let fingerprint_len = read_u32(&map, HEADER_SIZE) as usize;
+        let fingerprint = (HEADER_SIZE + 4, HEADER_SIZE + 4 + fingerprint_len);
+        let string_offsets = fingerprint.1;
+        let string_data = string_offsets + (num_strings + 1) * 4;
+        if map.len() < string_data {
+            return Err(InvalidMappedStore("Truncated string table").into())
+        }
+        let classes = string_data + read_u32(&map, string_offsets + num_strings * 4) as usize;
+        let fields = classes + num_classes * CLASS_RECORD * 4;
+        let methods = fields + num_fields * FIELD_RECORD * 4;
+        let end = methods + num_methods * METHOD_RECORD * 4;
+        if map.len() != end {
+            return Err(InvalidMappedStore("Unexpected length").into())
+        }
+        let result = MappedMappings {
+            map, fingerprint, num_strings, string_offsets, string_data,
+            num_classes, classes, num_fields, fields, num_methods, methods
+        };
+        // Validate all the strings upfront, so we never have to worry about invalid data later
+        str::from_utf8(&result.map[fingerprint.0..fingerprint.1])
+            .map_err(|_| InvalidMappedStore("Invalid UTF8"))?;
+        for index in 0..num_strings {
+            let start = result.string_offset(index);
+            let end = result.string_offset(index + 1);
+            if start > end || result.string_data + end > result.classes {
+                return Err(InvalidMappedStore("Invalid string offsets").into())
+            }
+            str::from_utf8(&result.map[(result.string_data + start)..(result.string_data + end)])
+                .map_err(|_| InvalidMappedStore("Invalid UTF8"))?;
+        }
+        let max_index = result.map[result.classes..].chunks(4)
+            .map(|bytes| read_u32(bytes, 0) as usize)
+            .max();
+        if max_index.map_or(false, |index| index >= num_strings) {
+            return Err(InvalidMappedStore("Invalid string index").into())
+        }
+        Ok(result)
+    }
+    /// The fingerprint of the data this store was computed from
+    #[inline]
+    pub fn fingerprint(&self) -> &str {
+        str::from_utf8(&self.map[self.fingerprint.0..self.fingerprint.1]).unwrap()
+    }
+    /// The size of the store's file in bytes
+    #[inline]
+    pub fn file_size(&self) -> usize {
+        self.map.len()
+    }
+    #[inline]
+    pub fn num_classes(&self) -> usize {
+        self.num_classes
+    }
+    #[inline]
+    pub fn num_fields(&self) -> usize {
+        self.num_fields
+    }
+    #[inline]
+    pub fn num_methods(&self) -> usize {
+        self.num_methods
+    }
+    pub fn get_class(&self, original: &str) -> Option<&str> {
+        let index = self.search(self.num_classes, |index| {
+            self.record_string(self.classes, CLASS_RECORD, index, 0).cmp(original)
+        })?;
+        Some(self.record_string(self.classes, CLASS_RECORD, index, 1))
+    }
+    pub fn get_field(&self, class: &str, name: &str) -> Option<&str> {
+        let index = self.search(self.num_fields, |index| {
+            let field = self.field(index);
+            (field.declaring_class, field.name).cmp(&(class, name))
+        })?;
+        Some(self.field(index).renamed)
+    }
+    pub fn get_method(&self, class: &str, name: &str, descriptor: &str) -> Option<&str> {
+        let index = self.search(self.num_methods, |index| {
+            let method = self.method(index);
+            (method.declaring_class, method.name, method.descriptor).cmp(&(class, name, descriptor))
+        })?;
+        Some(self.method(index).renamed)
+    }
+    pub fn classes<'a>(&'a self) -> impl Iterator<Item=(&'a str, &'a str)> + 'a {
+        (0..self.num_classes).map(move |index| {
+            (
+                self.record_string(self.classes, CLASS_RECORD, index, 0),
+                self.record_string(self.classes, CLASS_RECORD, index, 1)
+            )
+        })
+    }
+    pub fn fields<'a>(&'a self) -> impl Iterator<Item=MappedField<'a>> + 'a {
+        (0..self.num_fields).map(move |index| self.field(index))
+    }
+    pub fn methods<'a>(&'a self) -> impl Iterator<Item=MappedMethod<'a>> + 'a {
+        (0..self.num_methods).map(move |index| self.method(index))
+    }
+    /// Remap the class names in the specified descriptor
+    pub fn remap_descriptor(&self, descriptor: &str) -> String {
+        let mut result = String::with_capacity(descriptor.len());
+        let mut remaining = descriptor;
+        while let Some(start) = remaining.find('L') {
+            result.push_str(&remaining[..=start]);
+            remaining = &remaining[(start + 1)..];
+            let end = remaining.find(';').unwrap_or_else(|| remaining.len());
+            let name = &remaining[..end];
+            result.push_str(self.get_class(name).unwrap_or(name));
+            remaining = &remaining[end..];
+        }
+        result.push_str(remaining);
+        result
+    }
+    /// Serialize the store as SRG, without materializing the mappings
+    pub fn write_srg<W: Write>(&self, mut output: W) -> Result<(), Error> {
+        for (original, renamed) in self.classes() {
+            writeln!(output, "CL: {} {}", original, renamed)?;
+        }
+        for field in self.fields() {
+            let renamed_class = self.get_class(field.declaring_class)
+                .unwrap_or(field.declaring_class);
+            writeln!(
+                output, "FD: {}/{} {}/{}",
+                field.declaring_class, field.name,
+                renamed_class, field.renamed
+            )?;
+        }
+        for method in self.methods() {
+            let renamed_class = self.get_class(method.declaring_class)
+                .unwrap_or(method.declaring_class);
+            writeln!(
+                output, "MD: {}/{} {} {}/{} {}",
+                method.declaring_class, method.name, method.descriptor,
+                renamed_class, method.renamed, self.remap_descriptor(method.descriptor)
+            )?;
+        }
+        Ok(())
+    }
+    /// Serialize the store as TSRG, without materializing the mappings
+    ///
+    /// Since everything is sorted by class, we can group the members by merging the records.
+    pub fn write_tsrg<W: Write>(&self, mut output: W) -> Result<(), Error> {
+        let mut classes = self.classes().peekable();
+        let mut fields = self.fields().peekable();
+        let mut methods = self.methods().peekable();
+        loop {
+            let next_class = [
+                classes.peek().map(|&(original, _)| original),
+                fields.peek().map(|field| field.declaring_class),
+                methods.peek().map(|method| method.declaring_class)
+            ].iter().filter_map(|class| *class).min();
+            let class = match next_class {
+                Some(class) => class,
+                None => break
+            };
+            writeln!(output, "{} {}", class, self.get_class(class).unwrap_or(class))?;
+            if classes.peek().map_or(false, |&(original, _)| original == class) {
+                classes.next();
+            }
+            while fields.peek().map_or(false, |field| field.declaring_class == class) {
+                let field = fields.next().unwrap();
+                writeln!(output, "\t{} {}", field.name, field.renamed)?;
+            }
+            while methods.peek().map_or(false, |method| method.declaring_class == class) {
+                let method = methods.next().unwrap();
+                writeln!(output, "\t{} {} {}", method.name, method.descriptor, method.renamed)?;
+            }
+        }
+        Ok(())
+    }
+    /// Materialize the store as `FrozenMappings`
+    pub fn to_frozen(&self) -> FrozenMappings {
+        let mut builder = SimpleMappings::default();
+        for (original, renamed) in self.classes() {
+            builder.set_remapped_class(
+                ReferenceType::from_internal_name(original),
+                ReferenceType::from_internal_name(renamed)
+            );
+        }
+        for field in self.fields() {
+            builder.set_field_name(
+                FieldData::new(field.name.into(), ReferenceType::from_internal_name(field.declaring_class)),
+                field.renamed.into()
+            );
+        }
+        for method in self.methods() {
+            builder.set_method_name(
+                MethodData::new(
+                    method.name.into(),
+                    ReferenceType::from_internal_name(method.declaring_class),
+                    MethodSignature::from_descriptor(method.descriptor)
+                ),
+                method.renamed.into()
+            );
+        }
+        builder.frozen()
+    }
+    #[inline]
+    fn field(&self, index: usize) -> MappedField {
+        MappedField {
+            declaring_class: self.record_string(self.fields, FIELD_RECORD, index, 0),
+            name: self.record_string(self.fields, FIELD_RECORD, index, 1),
+            renamed: self.record_string(self.fields, FIELD_RECORD, index, 2),
+        }
+    }
+    #[inline]
+    fn method(&self, index: usize) -> MappedMethod {
+        MappedMethod {
+            declaring_class: self.record_string(self.methods, METHOD_RECORD, index, 0),
+            name: self.record_string(self.methods, METHOD_RECORD, index, 1),
+            descriptor: self.record_string(self.methods, METHOD_RECORD, index, 2),
+            renamed: self.record_string(self.methods, METHOD_RECORD, index, 3),
+        }
+    }
+    /// Binary search the records, using the specified comparison function
+    fn search<F: Fn(usize) -> Ordering>(&self, len: usize, func: F) -> Option<usize> {
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match func(middle) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Some(middle)
+            }
+        }
+        None
+    }
+    #[inline]
+    fn record_string(&self, table: usize, record_size: usize, index: usize, field: usize) -> &str {
+        let string_index = read_u32(&self.map, table + (index * record_size + field) * 4);
+        self.string(string_index as usize)
+    }
+    #[inline]
+    fn string_offset(&self, index: usize) -> usize {
+        read_u32(&self.map, self.string_offsets + index * 4) as usize
+    }
+    #[inline]
+    fn string(&self, index: usize) -> &str {
+        debug_assert!(index < self.num_strings);
+        let start = self.string_data + self.string_offset(index);
+        let end = self.string_data + self.string_offset(index + 1);
+        // NOTE: We validated all the strings when we opened the store
+        str::from_utf8(&self.map[start..end]).unwrap()
+    }
+}
+#[derive(Copy, Clone, Debug)]
+pub struct MappedField<'a> {
+    pub declaring_class: &'a str,
+    pub name: &'a str,
+    pub renamed: &'a str
+}
+#[derive(Copy, Clone, Debug)]
+pub struct MappedMethod<'a> {
+    pub declaring_class: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+    pub renamed: &'a str
+}
+
+#[inline]
+fn intern(strings: &mut IndexSet<String>, s: &str) -> u32 {
+    if let Some((index, _)) = strings.get_full(s) {
+        return index as u32
+    }
+    strings.insert(s.into());
+    (strings.len() - 1) as u32
+}
+#[inline]
+fn write_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&[
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8
+    ]);
+}
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let bytes = &data[offset..(offset + 4)];
+    u32::from(bytes[0])
+        | (u32::from(bytes[1]) << 8)
+        | (u32::from(bytes[2]) << 16)
+        | (u32::from(bytes[3]) << 24)
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid mapped store: {}", _0)]
+pub struct InvalidMappedStore(&'static str);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn roundtrip() {
+        let text = "CL: a net/minecraft/server/MinecraftServer\n\
+            FD: a/b net/minecraft/server/MinecraftServer/field_1234_b\n\
+            MD: a/c (La;)V net/minecraft/server/MinecraftServer/func_5678_c (Lnet/minecraft/server/MinecraftServer;)V\n";
+        let mappings = SrgMappingsFormat::parse_text(text).unwrap();
+        let location = ::std::env::temp_dir()
+            .join(format!("mapped-roundtrip-{}.mapped", ::std::process::id()));
+        MappedMappings::write(&mappings, "fingerprint", &location).unwrap();
+        let mapped = MappedMappings::open(&location).unwrap();
+        ::std::fs::remove_file(&location).unwrap();
+        assert_eq!(mapped.fingerprint(), "fingerprint");
+        assert_eq!(mapped.get_class("a"), Some("net/minecraft/server/MinecraftServer"));
+        assert_eq!(mapped.get_field("a", "b"), Some("field_1234_b"));
+        assert_eq!(mapped.get_method("a", "c", "(La;)V"), Some("func_5678_c"));
+        assert_eq!(mapped.get_method("a", "c", "()V"), None);
+        let mut serialized = Vec::new();
+        mapped.write_srg(&mut serialized).unwrap();
+        assert_eq!(String::from_utf8(serialized).unwrap(), SrgMappingsFormat::write_string(&mappings));
+    }
+    #[test]
+    fn lookups() {
+        // The records are out of order, so we know they're sorted properly
+        let text = "CL: c net/minecraft/server/World\n\
+            CL: a net/minecraft/server/MinecraftServer\n\
+            CL: b net/minecraft/server/Entity\n\
+            CL: aa net/minecraft/server/Block\n\
+            FD: c/a net/minecraft/server/World/field_3_a\n\
+            FD: a/b net/minecraft/server/MinecraftServer/field_1_b\n\
+            FD: a/a net/minecraft/server/MinecraftServer/field_2_a\n\
+            FD: b/a net/minecraft/server/Entity/field_4_a\n\
+            MD: b/a (I)V net/minecraft/server/Entity/func_4_a (I)V\n\
+            MD: a/c (La;)V net/minecraft/server/MinecraftServer/func_1_c (Lnet/minecraft/server/MinecraftServer;)V\n\
+            MD: a/c ()V net/minecraft/server/MinecraftServer/func_2_c ()V\n\
+            MD: a/c (I)La; net/minecraft/server/MinecraftServer/func_3_c (I)Lnet/minecraft/server/MinecraftServer;\n\
+            MD: a/b ()V net/minecraft/server/MinecraftServer/func_5_b ()V\n\
+            MD: c/a ()V net/minecraft/server/World/func_6_a ()V\n";
+        let mappings = SrgMappingsFormat::parse_text(text).unwrap();
+        let location = ::std::env::temp_dir()
+            .join(format!("mapped-lookups-{}.mapped", ::std::process::id()));
+        MappedMappings::write(&mappings, "fingerprint", &location).unwrap();
+        let mapped = MappedMappings::open(&location).unwrap();
+        ::std::fs::remove_file(&location).unwrap();
+        assert_eq!((mapped.num_classes(), mapped.num_fields(), mapped.num_methods()), (4, 4, 6));
+        for &(original, renamed) in &[
+            ("a", "net/minecraft/server/MinecraftServer"), ("aa", "net/minecraft/server/Block"),
+            ("b", "net/minecraft/server/Entity"), ("c", "net/minecraft/server/World")
+        ] {
+            assert_eq!(mapped.get_class(original), Some(renamed), "{}", original);
+        }
+        assert_eq!(mapped.get_class("d"), None);
+        assert_eq!(mapped.get_class(""), None);
+        for &(class, name, renamed) in &[
+            ("a", "a", "field_2_a"), ("a", "b", "field_1_b"),
+            ("b", "a", "field_4_a"), ("c", "a", "field_3_a")
+        ] {
+            assert_eq!(mapped.get_field(class, name), Some(renamed), "{}/{}", class, name);
+        }
+        assert_eq!(mapped.get_field("aa", "a"), None);
+        assert_eq!(mapped.get_field("b", "b"), None);
+        for &(class, name, descriptor, renamed) in &[
+            ("a", "b", "()V", "func_5_b"), ("a", "c", "()V", "func_2_c"),
+            ("a", "c", "(I)La;", "func_3_c"), ("a", "c", "(La;)V", "func_1_c"),
+            ("b", "a", "(I)V", "func_4_a"), ("c", "a", "()V", "func_6_a")
+        ] {
+            assert_eq!(mapped.get_method(class, name, descriptor), Some(renamed), "{}/{}{}", class, name, descriptor);
+        }
+        assert_eq!(mapped.get_method("a", "c", "(J)V"), None);
+        assert_eq!(mapped.get_method("b", "a", "()V"), None);
+        // Iteration follows the sorted order of the records
+        let classes: Vec<&str> = mapped.classes().map(|(original, _)| original).collect();
+        assert_eq!(classes, vec!["a", "aa", "b", "c"]);
+        let methods: Vec<&str> = mapped.methods().map(|method| method.renamed).collect();
+        assert_eq!(methods, vec!["func_5_b", "func_2_c", "func_3_c", "func_1_c", "func_4_a", "func_6_a"]);
+    }
+}
@@ -7,6 +7,7 @@ use std::path::{PathBuf, Path};
 use std::sync::Arc;
 
 use zip::ZipArchive;
+use zip::result::ZipError;
 use indexmap::{IndexMap};
 use failure::Error;
 use failure_derive::Fail;
@@ -14,149 +15,302 @@ use itertools::PeekingNext;
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 use serde::de::{self, Deserialize, Deserializer, SeqAccess, MapAccess};
 use serde_derive::{Deserialize, Serialize};
-use crossbeam::atomic::ArcCell;
-use parking_lot::{Mutex};
 use srglib::prelude::*;
 
-use crate::utils::LruCache;
+use crate::utils::{LruCache, LruStats, LoadingCache, Downloader, write_atomic};
+use crate::manifest::CacheManifest;
+use crate::snapshot::load_srg_file;
 use crate::MinecraftVersion;
+use crate::cache::NotCachedOffline;
+use crate::mirrors::Upstream;
+use crate::mcp_config::{McpConfig, MissingMcpConfig, MCP_CONFIG_FILES};
 
-const MAXIMUM_CACHE_SIZE: usize = 32;
+/// The approximate number of bytes of loaded MCP names we keep in memory
+const MCP_CACHE_BUDGET: usize = 256 * 1024 * 1024;
+/// The approximate number of bytes of loaded srg mappings we keep in memory
+const SRG_CACHE_BUDGET: usize = 256 * 1024 * 1024;
+/// The files we cache for each minecraft version's srg mappings, using the old system
+pub(crate) const SRG_FILES: [&str; 1] = ["joined-mcp.srg"];
+/// The files we cache for each minecraft version's srg mappings, using the new config system
+pub(crate) const TSRG_FILES: [&str; 1] = ["joined.tsrg"];
+/// The files we cache for each MCP version
+pub(crate) const MCP_FILES: [&str; 3] = ["fields.csv", "methods.csv", "params.csv"];
 /// The first version where we use the new `mcp-config` system.
 ///
-/// The old system, we fetched SRG data from
-/// `"http://files.minecraftforge.net/maven/de/oceanlabs/mcp/mcp/{minecraft_version}/mcp-{minecraft_veresion}-srg.zip"`.
+/// The old system, we fetched SRG data from the forge maven at
+/// `"de/oceanlabs/mcp/mcp/{minecraft_version}/mcp-{minecraft_veresion}-srg.zip"`.
 /// These gave us a zip file containing an old-fashioned SRG file that was named `joined.srg`
 ///
-/// Under the new "mcp-config" system, we fetch SRG data from
-/// `"http://files.minecraftforge.net/maven/de/oceanlabs/mcp/mcp_config/{minecraft_version}/mcp_config-{minecraft_version}.zip"`.
+/// Under the new "mcp-config" system, we fetch SRG data from the forge maven at
+/// `"de/oceanlabs/mcp/mcp_config/{minecraft_version}/mcp_config-{minecraft_version}.zip"`.
 /// This gives us a zip file where the SRG data is located in `config/joined.tsrg`.
 /// It uses the newer and more efficient TSRG format.
-const CONFIG_SYSTEM_FIRST_VERSION: MinecraftVersion = MinecraftVersion { major: 1, minor: 13, patch: 0 };
+const CONFIG_SYSTEM_FIRST_VERSION: MinecraftVersion = MinecraftVersion::release(1, 13, 0);
+
+/// The format MCP publishes a minecraft version's srg data in
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SrgFormat {
+    /// The old-fashioned SRG format, used before the config system
+    Srg,
+    /// The more compact TSRG format, used by the config system
+    Tsrg
+}
+impl SrgFormat {
+    #[inline]
+    pub fn of_version(version: MinecraftVersion) -> SrgFormat {
+        if version >= CONFIG_SYSTEM_FIRST_VERSION {
+            SrgFormat::Tsrg
+        } else {
+            SrgFormat::Srg
+        }
+    }
+}
+/// The files we cache for the specified version's srg mappings, which depend on its native format
+#[inline]
+pub(crate) fn srg_files(version: MinecraftVersion) -> &'static [&'static str] {
+    match SrgFormat::of_version(version) {
+        SrgFormat::Srg => &SRG_FILES,
+        SrgFormat::Tsrg => &TSRG_FILES,
+    }
+}
 
 #[derive(Fail, Debug)]
 #[fail(display = "Unknown MCP version {:?}", _0)]
 pub struct UnknownMcpVersion(McpVersion);
 
+/// Indicates the MCP version wasn't exported for the requested minecraft version
+#[derive(Fail, Debug)]
+pub struct IncompatibleMcpVersion {
+    pub version: McpVersionSpec,
+    pub minecraft_version: MinecraftVersion,
+    /// The minecraft versions the MCP version was actually exported for
+    pub available: Vec<MinecraftVersion>
+}
+impl Display for IncompatibleMcpVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MCP {} isn't available for minecraft {}, only for ", self.version, self.minecraft_version)?;
+        for (index, version) in self.available.iter().enumerate() {
+            if index > 0 { f.write_str(", ")? }
+            write!(f, "{}", version)?;
+        }
+        Ok(())
+    }
+}
+
 pub(crate) struct McpVersionCache {
     versions: McpVersionList,
-    srg_mapping_versions: ArcCell<IndexMap<MinecraftVersion, FrozenMappings>>,
-    loaded_versions: ArcCell<LruCache<McpVersion, LoadedVersion>>,
-    lock: Mutex<()>,
-    cache_location: PathBuf
+    srg_mapping_versions: LoadingCache<LruCache<MinecraftVersion, FrozenMappings>>,
+    configs: LoadingCache<IndexMap<MinecraftVersion, Arc<McpConfig>>>,
+    loaded_versions: LoadingCache<LruCache<McpVersionSpec, LoadedVersion>>,
+    cache_location: PathBuf,
+    downloader: Downloader
 }
 impl McpVersionCache {
-    pub fn setup(cache_location: PathBuf) -> Result<McpVersionCache, Error> {
+    pub fn setup(cache_location: PathBuf, downloader: Downloader) -> Result<McpVersionCache, Error> {
         assert!(cache_location.exists());
-        // NOTE: We never cache since we want the latest info
-        let versions = McpVersionList::download()?;
+        migrate_stable_directories(&cache_location);
+        /*
+         * NOTE: We always download the version list when we're online since we want the latest info.
+         * However, we still persist it so we have something to fall back on when we're offline.
+         */
+        let versions_file = cache_location.join("versions.json");
+        let versions = if downloader.is_offline() {
+            McpVersionList::load(&versions_file)?
+        } else {
+            let versions = McpVersionList::download(&downloader)?;
+            write_atomic(&versions_file, |writer| {
+                ::serde_json::to_writer(writer, &versions)?;
+                Ok(())
+            })?;
+            versions
+        };
         Ok(McpVersionCache {
-            versions, srg_mapping_versions: ArcCell::default(),
-            loaded_versions: ArcCell::new(Arc::new(LruCache::new(MAXIMUM_CACHE_SIZE))),
-            lock: Mutex::new(()),
-            cache_location
+            versions,
+            srg_mapping_versions: LoadingCache::new(LruCache::new(SRG_CACHE_BUDGET)),
+            configs: LoadingCache::default(),
+            loaded_versions: LoadingCache::new(LruCache::new(MCP_CACHE_BUDGET)),
+            cache_location, downloader
         })
     }
     pub fn load_srg_mappings(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
-        if let Some(srg_mappings) = self.srg_mapping_versions.get().get(&version) {
-            return Ok(srg_mappings.clone())
-        }
-        self.load_srg_mappings_fallback(version)
+        self.srg_mapping_versions.load_sized(&version, || {
+            let mappings_file = self.fetch_srg_mappings(version)?;
+            let mappings = load_srg_file(mappings_file.parent().unwrap(), srg_files(version)[0])?;
+            let size = approximate_srg_size(&mappings);
+            Ok((mappings, size))
+        })
     }
-    #[cold]
-    fn load_srg_mappings_fallback(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
-        // This ensures we're only loading one mapping at a time
-        let _guard = self.lock.lock();
-        let srg_mapping_versions = self.srg_mapping_versions.get();
-        /*
-         * Now that we have the lock,
-         * let's check again if our version is present.
-         * Someone else could've already loaded it while we were blocking
-         */
-        if let Some(loaded) = srg_mapping_versions.get(&version) {
-            return Ok(loaded.clone());
-        }
-        let mut updated_srg_mapping_versions = (*srg_mapping_versions).clone();
-        drop(srg_mapping_versions); // We're invalidating this
+    /// Ensure the srg mappings for the specified version are cached, downloading them if needed
+    ///
+    /// Returns the location of the cached mappings file.
+    /// Since all the writes are atomic, this is safe to call concurrently.
+    pub fn fetch_srg_mappings(&self, version: MinecraftVersion) -> Result<PathBuf, Error> {
         let version_directory = self.cache_location
             .join(format!("versions/{}", version));
-        let mappings_file = version_directory.join("joined-mcp.srg");
-        if !mappings_file.exists() {
-            if version >= CONFIG_SYSTEM_FIRST_VERSION {
-                fs::create_dir_all(&version_directory)?;
-                let url = format!(
-                    "http://files.minecraftforge.net/maven/de/oceanlabs/mcp/mcp_config/{0}/mcp_config-{0}.zip",
-                    version
-                );
-                let buffer = crate::utils::download_buffer(&url)?;
-                let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
-                let entry = archive.by_name("config/joined.tsrg")?;
-                // For consistency with the old system, we need to translate from TSRG to SRG
-                let mappings = TabSrgMappingsFormat::parse_stream(BufReader::new(entry))?;
-                let mut file = File::create(&mappings_file)?;
-                SrgMappingsFormat::write(&mappings, &mut file)?;
-            } else {
-                fs::create_dir_all(&version_directory)?;
-                let url = format!(
-                    "http://files.minecraftforge.net/maven/de/oceanlabs/mcp/mcp/{0}/mcp-{0}-srg.zip",
-                    version
-                );
-                let buffer = crate::utils::download_buffer(&url)?;
-                let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
-                let mut entry = archive.by_name("joined.srg")?;
-                let mut file = File::create(&mappings_file)?;
-                copy(&mut entry, &mut file)?;
+        let files = srg_files(version);
+        let mappings_file = version_directory.join(files[0]);
+        if CacheManifest::check_valid(&version_directory, files, &self.downloader)? {
+            return Ok(mappings_file)
+        }
+        fs::create_dir_all(&version_directory)?;
+        let mut manifest = CacheManifest::new();
+        if version >= CONFIG_SYSTEM_FIRST_VERSION {
+            let path = mcp_config_path(version);
+            let buffer = self.downloader.download_buffer(Upstream::ForgeMaven, &path)?;
+            manifest.record_source(&path, &buffer);
+            let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
+            // We keep the TSRG as-is, so we don't lose anything by converting it
+            let mut entry = archive.by_name("config/joined.tsrg")?;
+            write_atomic(&mappings_file, |writer| {
+                copy(&mut entry, writer)?;
+                Ok(())
+            })?;
+        } else {
+            let path = format!(
+                "de/oceanlabs/mcp/mcp/{0}/mcp-{0}-srg.zip",
+                version
+            );
+            let buffer = self.downloader.download_buffer(Upstream::ForgeMaven, &path)?;
+            manifest.record_source(&path, &buffer);
+            let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
+            let mut entry = archive.by_name("joined.srg")?;
+            write_atomic(&mappings_file, |writer| {
+                copy(&mut entry, writer)?;
+                Ok(())
+            })?;
+        }
+        manifest.record_file(&version_directory, files[0])?;
+        manifest.save(&version_directory)?;
+        Ok(mappings_file)
+    }
+    /// The native format of the srg data for the specified version
+    #[inline]
+    pub fn srg_format(&self, version: MinecraftVersion) -> SrgFormat {
+        SrgFormat::of_version(version)
+    }
+    /// Load the rest of the `mcp_config` artifact for the specified version
+    ///
+    /// This is only available for the versions using the new config system.
+    pub fn load_config(&self, version: MinecraftVersion) -> Result<Arc<McpConfig>, Error> {
+        if version < CONFIG_SYSTEM_FIRST_VERSION {
+            return Err(MissingMcpConfig(version).into())
+        }
+        self.configs.load(&version, || {
+            let config_directory = self.fetch_config(version)?;
+            Ok(Arc::new(McpConfig::load(&config_directory)?))
+        })
+    }
+    /// Ensure the rest of the `mcp_config` artifact is extracted, separately from the srg mappings
+    ///
+    /// The srg mappings don't depend on any of this, so a broken config only affects the config itself.
+    /// Files that are missing from the artifact are treated as empty.
+    fn fetch_config(&self, version: MinecraftVersion) -> Result<PathBuf, Error> {
+        let config_directory = self.cache_location
+            .join(format!("versions/{}/config", version));
+        if CacheManifest::check_valid(&config_directory, &MCP_CONFIG_FILES, &self.downloader)? {
+            return Ok(config_directory)
+        }
+        fs::create_dir_all(&config_directory)?;
+        let path = mcp_config_path(version);
+        let buffer = self.downloader.download_buffer(Upstream::ForgeMaven, &path)?;
+        let mut manifest = CacheManifest::new();
+        manifest.record_source(&path, &buffer);
+        let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
+        for &name in &MCP_CONFIG_FILES {
+            let location = config_directory.join(name);
+            match archive.by_name(&format!("config/{}", name)) {
+                Ok(mut entry) => write_atomic(&location, |writer| {
+                    copy(&mut entry, writer)?;
+                    Ok(())
+                })?,
+                Err(ZipError::FileNotFound) => write_atomic(&location, |_| Ok(()))?,
+                Err(e) => return Err(e.into())
             }
+            manifest.record_file(&config_directory, name)?;
         }
-        let mappings = SrgMappingsFormat::parse_stream(BufReader::new(File::open(&mappings_file)?))?;
-        updated_srg_mapping_versions.insert(version, mappings.clone());
-        self.srg_mapping_versions.set(Arc::new(updated_srg_mapping_versions));
-        Ok(mappings)
+        manifest.save(&config_directory)?;
+        Ok(config_directory)
     }
 
-    pub fn load_mappings(&self, version: McpVersion) -> Result<Arc<McpMappings>, Error> {
-        if let Some(loaded) = self.loaded_versions.get().get(&version) {
-            return Ok(loaded.mappings.clone());
+    pub fn load_mappings(&self, spec: McpVersionSpec) -> Result<Arc<McpMappings>, Error> {
+        let loaded = self.loaded_versions.load_sized(&spec, || {
+            let version_info = self.versions.find_version(spec.version)
+                .ok_or_else(|| UnknownMcpVersion(spec.version))?;
+            let version_directory = self.fetch_mappings(version_info, spec.nodoc)?;
+            let fields_file = version_directory.join("fields.csv");
+            let methods_file = version_directory.join("methods.csv");
+            let params_file = version_directory.join("params.csv");
+            let mut mappings = McpMappings::new();
+            mappings.load_fields(&mut ::csv::Reader::from_path(fields_file)?)?;
+            mappings.load_methods(&mut ::csv::Reader::from_path(methods_file)?)?;
+            mappings.load_params(&mut ::csv::Reader::from_path(params_file)?)?;
+            let size = mappings.approximate_size();
+            Ok((LoadedVersion { version_info, mappings: Arc::new(mappings) }, size))
+        })?;
+        Ok(loaded.mappings)
+    }
+    /// The usage of the in-memory caches of the srg mappings and MCP names
+    pub fn memory_stats(&self) -> (LruStats, LruStats) {
+        (self.srg_mapping_versions.values().stats(), self.loaded_versions.values().stats())
+    }
+    /// Ensure the MCP mappings for the specified version are cached, without loading them
+    pub fn fetch_version(&self, spec: McpVersionSpec) -> Result<(), Error> {
+        let version_info = self.versions.find_version(spec.version)
+            .ok_or_else(|| UnknownMcpVersion(spec.version))?;
+        self.fetch_mappings(version_info, spec.nodoc)?;
+        Ok(())
+    }
+    /// Check that the specified MCP version was exported for the specified minecraft version
+    pub fn check_compatible(
+        &self,
+        version: McpVersion,
+        minecraft_version: MinecraftVersion
+    ) -> Result<(), Error> {
+        if self.versions.find_version_for(version, minecraft_version).is_some() {
+            return Ok(())
         }
-        self.load_mappings_fallback(version)
-    }
-    #[cold]
-    fn load_mappings_fallback(&self, version: McpVersion) -> Result<Arc<McpMappings>, Error> {
-        let version_info = self.versions.find_version(version)
-            .ok_or_else(|| UnknownMcpVersion(version))?;
-        // This lock guarantees that only one person will be loading MCP versions at a time
-        let _guard = self.lock.lock();
-        let loaded_versions = self.loaded_versions.get();
-        /*
-         * Now that we have the lock,
-         * let's check again if our version is present.
-         * Someone else could've already loaded it while we were blocking
-         */
-        if let Some(loaded) = loaded_versions.get(&version) {
-            return Ok(loaded.mappings.clone());
+        let available = self.versions.minecraft_versions_for(version);
+        if available.is_empty() {
+            Err(UnknownMcpVersion(version).into())
+        } else {
+            Err(IncompatibleMcpVersion {
+                version: version.create_spec(false),
+                minecraft_version, available
+            }.into())
         }
-        drop(loaded_versions); // We're invalidating this
+    }
+    /// Resolve the specified request into an exact version, using the versions for the minecraft version
+    pub fn resolve_version(
+        &self,
+        request: McpVersionRequest,
+        minecraft_version: MinecraftVersion
+    ) -> Result<McpVersionSpec, UnresolvedMcpVersion> {
+        self.versions.resolve(request, minecraft_version)
+            .map(|version| version.create_spec(request.nodoc))
+            .ok_or(UnresolvedMcpVersion { request, minecraft_version })
+    }
+    /// All the minecraft versions MCP knows about
+    pub fn known_minecraft_versions(&self) -> Vec<MinecraftVersion> {
+        self.versions.0.keys().cloned().collect()
+    }
+    /// All the MCP versions we know about, from every minecraft version
+    pub fn known_versions(&self) -> Vec<McpVersion> {
+        self.versions.iter().map(|info| info.version).collect()
+    }
+    /// Ensure the MCP mappings for the specified version are cached, downloading them if needed
+    ///
+    /// Returns the directory containing the cached CSV files.
+    /// The documented and undocumented exports are cached separately,
+    /// since the undocumented ones are significantly smaller.
+    fn fetch_mappings(&self, version_info: McpVersionInfo, nodoc: bool) -> Result<PathBuf, Error> {
         let version_directory = self.cache_location
-            .join(format!("{}", version.create_spec(true)));
-        fs::create_dir_all(&version_directory)?;
-        let fields_file = version_directory.join("fields.csv");
-        let methods_file = version_directory.join("methods.csv");
-        if !fields_file.exists() || !methods_file.exists() {
-            version_info.download_into(&fields_file, &methods_file, true)?
+            .join(format!("{}", version_info.version.create_spec(nodoc)));
+        if !CacheManifest::check_valid(&version_directory, &MCP_FILES, &self.downloader)? {
+            fs::create_dir_all(&version_directory)?;
+            version_info.download_into(&self.downloader, &version_directory, nodoc)?;
         }
-        let mut mappings = McpMappings::new();
-        mappings.load_fields(&mut ::csv::Reader::from_path(fields_file)?)?;
-        mappings.load_methods(&mut ::csv::Reader::from_path(methods_file)?)?;
-        let mappings = Arc::new(mappings);
-
-        let mut updated_loaded_versions =
-            LruCache::clone(&self.loaded_versions.get());
-        updated_loaded_versions.insert(version, LoadedVersion {
-            version_info,
-            mappings: mappings.clone()
-        });
-        self.loaded_versions.set(Arc::new(updated_loaded_versions));
-        Ok(mappings)
+        Ok(version_directory)
     }
 }
 #[derive(Clone)]
@@ -167,32 +321,163 @@ struct LoadedVersion {
 #[derive(Debug)]
 pub struct McpMappings {
     pub fields: IndexMap<String, String>,
-    pub methods: IndexMap<String, String>
+    pub methods: IndexMap<String, String>,
+    /// The javadoc of the fields and methods, keyed by their srg name
+    ///
+    /// This is always empty for `nodoc` versions.
+    pub docs: IndexMap<String, String>,
+    /// The names of the method parameters, keyed by their srg name (like `p_12345_1_`)
+    pub params: IndexMap<String, String>,
+    /// The side of the fields and methods, keyed by their srg name
+    pub sides: IndexMap<String, McpSide>,
+    /// The parameters of each method, keyed by the numeric id of its srg name
+    method_params: IndexMap<u32, Vec<(u32, String)>>
 }
 impl McpMappings {
     #[inline]
     pub fn new() -> Self {
         McpMappings {
             fields: IndexMap::new(),
-            methods: IndexMap::new()
+            methods: IndexMap::new(),
+            docs: IndexMap::new(),
+            params: IndexMap::new(),
+            sides: IndexMap::new(),
+            method_params: IndexMap::new()
         }
     }
+    /// Estimate the number of bytes these names use in memory
+    pub fn approximate_size(&self) -> usize {
+        fn map_size<K, V, F: Fn(&K, &V) -> usize>(map: &IndexMap<K, V>, entry_size: F) -> usize {
+            map.iter().map(|(key, value)| ENTRY_OVERHEAD + entry_size(key, value)).sum()
+        }
+        let strings = |key: &String, value: &String| key.len() + value.len();
+        map_size(&self.fields, strings) + map_size(&self.methods, strings)
+            + map_size(&self.docs, strings) + map_size(&self.params, strings)
+            + map_size(&self.sides, |key, _| key.len())
+            + map_size(&self.method_params, |_, params| {
+                params.iter().map(|&(_, ref name)| ENTRY_OVERHEAD + name.len()).sum()
+            })
+    }
+    /// Get the documentation of the field or method with the specified srg name
+    #[inline]
+    pub fn doc(&self, srg_name: &str) -> Option<&str> {
+        self.docs.get(srg_name).map(String::as_str)
+    }
+    /// Get the side of the field or method with the specified srg name
+    #[inline]
+    pub fn side(&self, srg_name: &str) -> Option<McpSide> {
+        self.sides.get(srg_name).cloned()
+    }
+    /// Get the parameters of the method with the specified srg name,
+    /// as pairs of their local variable index and their name.
+    ///
+    /// Constructor parameters aren't included, since constructors don't have srg names.
+    pub fn method_params(&self, srg_name: &str) -> &[(u32, String)] {
+        parse_method_id(srg_name)
+            .and_then(|id| self.method_params.get(&id))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
     fn load_fields<R: Read>(&mut self, reader: &mut ::csv::Reader<R>) -> Result<(), ::csv::Error> {
-        self.fields = load_record_map(reader)?;
+        self.fields = self.load_record_map(reader)?;
         Ok(())
     }
     fn load_methods<R: Read>(&mut self, reader: &mut ::csv::Reader<R>) -> Result<(), ::csv::Error> {
-        self.methods = load_record_map(reader)?;
+        self.methods = self.load_record_map(reader)?;
         Ok(())
     }
+    fn load_params<R: Read>(&mut self, reader: &mut ::csv::Reader<R>) -> Result<(), ::csv::Error> {
+        for entry in reader.deserialize::<ParamEntry>() {
+            let entry = entry?;
+            if let Some((method, index)) = parse_param_id(&entry.param) {
+                self.method_params.entry(method).or_insert_with(Vec::new)
+                    .push((index, entry.name.clone()));
+            }
+            self.params.insert(entry.param, entry.name);
+        }
+        for params in self.method_params.values_mut() {
+            params.sort_by_key(|&(index, _)| index);
+        }
+        Ok(())
+    }
+    fn load_record_map<R: Read>(
+        &mut self,
+        reader: &mut ::csv::Reader<R>
+    ) -> Result<IndexMap<String, String>, ::csv::Error> {
+        let mut result = IndexMap::new();
+        for entry in reader.deserialize::<MappingEntry>() {
+            let entry = entry?;
+            // The nodoc exports still have the column, it's just always empty
+            if !entry.desc.is_empty() {
+                self.docs.insert(entry.searge.clone(), entry.desc);
+            }
+            self.sides.insert(entry.searge.clone(), McpSide::from_id(entry.side));
+            result.insert(entry.searge, entry.name);
+        }
+        Ok(result)
+    }
 }
-fn load_record_map<R: Read>(
-    reader: &mut ::csv::Reader<R>
-) -> Result<IndexMap<String, String>, ::csv::Error> {
-    reader.deserialize::<MappingEntry>()
-        .map(|result| {
-            result.map(|entry| (entry.searge, entry.name))
-        }).collect()
+/// The side of the game a member is present on
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpSide {
+    Client,
+    Server,
+    Both
+}
+impl McpSide {
+    /// Parse the side from the numeric id used in the MCP exports
+    #[inline]
+    fn from_id(id: u32) -> McpSide {
+        match id {
+            0 => McpSide::Client,
+            1 => McpSide::Server,
+            // Treat anything unexpected as being on both sides, so we never hide it
+            _ => McpSide::Both
+        }
+    }
+    #[inline]
+    pub fn is_client(self) -> bool {
+        self != McpSide::Server
+    }
+    #[inline]
+    pub fn is_server(self) -> bool {
+        self != McpSide::Client
+    }
+}
+#[inline]
+fn mcp_config_path(version: MinecraftVersion) -> String {
+    format!("de/oceanlabs/mcp/mcp_config/{0}/mcp_config-{0}.zip", version)
+}
+/// The approximate bytes used by each entry of a map, excluding the contents of its strings
+const ENTRY_OVERHEAD: usize = 64;
+/// Estimate the number of bytes the srg mappings use in memory
+///
+/// Each mapping holds the original and renamed data, along with the strings they reference.
+fn approximate_srg_size(mappings: &FrozenMappings) -> usize {
+    let entries = mappings.classes().count() + mappings.fields().count() + mappings.methods().count();
+    entries * 4 * ENTRY_OVERHEAD
+}
+/// Parse the numeric id of a srg method name like `func_12345_a`
+fn parse_method_id(srg_name: &str) -> Option<u32> {
+    if !srg_name.starts_with("func_") { return None }
+    srg_name["func_".len()..].split('_').next()?.parse().ok()
+}
+/// Parse the method id and local variable index of a srg parameter name like `p_12345_1_`
+///
+/// Constructor parameters (like `p_i12345_1_`) are ignored.
+fn parse_param_id(srg_name: &str) -> Option<(u32, u32)> {
+    if !srg_name.starts_with("p_") { return None }
+    let mut parts = srg_name["p_".len()..].split('_');
+    let method = parts.next()?.parse().ok()?;
+    let index = parts.next()?.parse().ok()?;
+    Some((method, index))
+}
+#[derive(Debug, Deserialize)]
+struct ParamEntry {
+    param: String,
+    name: String,
+    side: u32
 }
 #[derive(Debug, Deserialize)]
 struct MappingEntry {
@@ -203,17 +488,58 @@ struct MappingEntry {
 }
 
 /// The mcp version info taken from `http://export.mcpbot.bspk.rs/versions.json`
-#[derive(Debug, Deserialize)]
-struct McpVersionList(IndexMap<MinecraftVersion, ChannelVersionInfo>);
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct McpVersionList(IndexMap<MinecraftVersion, ChannelVersionInfo>);
 impl McpVersionList {
-    pub fn download() -> Result<McpVersionList, Error> {
-        let buffer = crate::utils::download_buffer("http://export.mcpbot.bspk.rs/versions.json")?;
+    pub fn download(downloader: &Downloader) -> Result<McpVersionList, Error> {
+        let buffer = downloader.download_buffer(Upstream::McpBotExport, "versions.json")?;
         Ok(::serde_json::from_slice(&buffer)?)
     }
+    /// Load the last-known version list, which we persist whenever we download it
+    pub fn load(location: &Path) -> Result<McpVersionList, Error> {
+        if !location.exists() {
+            return Err(NotCachedOffline("The MCP version list".into()).into())
+        }
+        Ok(::serde_json::from_reader(BufReader::new(File::open(location)?))?)
+    }
+    /// Find the specified version, which may belong to any minecraft version
     #[inline]
     pub fn find_version(&self, version: McpVersion) -> Option<McpVersionInfo> {
         self.iter().find(|v| v.version == version)
     }
+    /// Find the specified version, only if it was exported for the specified minecraft version
+    #[inline]
+    pub fn find_version_for(&self, version: McpVersion, minecraft_version: MinecraftVersion) -> Option<McpVersionInfo> {
+        self.iter().find(|v| v.version == version && v.minecraft_version == minecraft_version)
+    }
+    /// All the minecraft versions the specified version was exported for
+    pub fn minecraft_versions_for(&self, version: McpVersion) -> Vec<MinecraftVersion> {
+        self.iter().filter(|v| v.version == version)
+            .map(|v| v.minecraft_version)
+            .collect()
+    }
+    /// Resolve the specified request against the versions for the specified minecraft version
+    ///
+    /// Exact requests are returned as-is, even if they belong to a different minecraft version.
+    pub fn resolve(&self, request: McpVersionRequest, minecraft_version: MinecraftVersion) -> Option<McpVersion> {
+        let value = match request.selector {
+            McpVersionSelector::Exact(value) => value,
+            McpVersionSelector::Latest | McpVersionSelector::Before(_) => {
+                let channel_versions = self.0.get(&minecraft_version)?;
+                let values = match request.channel {
+                    McpChannel::Snapshot => &channel_versions.snapshot,
+                    McpChannel::Stable => &channel_versions.stable,
+                };
+                values.iter().cloned()
+                    .filter(|&value| match request.selector {
+                        McpVersionSelector::Before(limit) => value < limit,
+                        _ => true
+                    })
+                    .max()?
+            }
+        };
+        Some(McpVersion { value, channel: request.channel })
+    }
     pub fn iter<'a>(&'a self) -> impl Iterator<Item=(McpVersionInfo)> + 'a {
         self.0.iter().flat_map(|(&minecraft_version, channel_versions)| {
             channel_versions.snapshot.iter()
@@ -226,7 +552,7 @@ impl McpVersionList {
         })
     }
 }
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 struct ChannelVersionInfo {
     snapshot: Vec<u32>,
     stable: Vec<u32>
@@ -254,31 +580,64 @@ impl Display for McpChannel {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.write_str(match *self {
             McpChannel::Snapshot => "snapshot",
-            McpChannel::Stable => "Stable",
+            McpChannel::Stable => "stable",
         })
     }
 }
 
+/// Rename the directories of stable exports that were cached as `Stable_*`,
+/// from before the channel was displayed in lowercase like it is in the export URLs.
+///
+/// This is best-effort, since the cache may be read-only.
+fn migrate_stable_directories(cache_location: &Path) {
+    let entries = match fs::read_dir(cache_location) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) if name.starts_with("Stable_") => name,
+            _ => continue
+        };
+        let renamed = cache_location.join(format!("stable{}", &name["Stable".len()..]));
+        let _ = if renamed.exists() {
+            // We've already downloaded it again under the new name
+            fs::remove_dir_all(entry.path())
+        } else {
+            fs::rename(entry.path(), &renamed)
+        };
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct McpVersionInfo { // TODO: Rename to ResolvedMcpVersion
     minecraft_version: MinecraftVersion,
     version: McpVersion
 }
 impl McpVersionInfo {
-    fn download_into(&self, fields_file: &Path, methods_file: &Path, nodoc: bool) -> Result<(), Error> {
-        let url = self.download_zip_url(nodoc);
-        let buffer = crate::utils::download_buffer(&url)?;
+    fn download_into(&self, downloader: &Downloader, directory: &Path, nodoc: bool) -> Result<(), Error> {
+        let path = self.download_zip_path(nodoc);
+        let buffer = downloader.download_buffer(Upstream::McpBotExport, &path)?;
+        let mut manifest = CacheManifest::new();
+        manifest.record_source(&path, &buffer);
         let mut archive = ZipArchive::new(Cursor::new(&buffer))?;
-        let mut fields_file = File::create(fields_file)?;
-        let mut methods_file = File::create(methods_file)?;
-        copy(&mut archive.by_name("fields.csv")?, &mut fields_file)?;
-        copy(&mut archive.by_name("methods.csv")?, &mut methods_file)?;
+        for &name in &MCP_FILES {
+            let mut entry = archive.by_name(name)?;
+            write_atomic(&directory.join(name), |writer| {
+                copy(&mut entry, writer)?;
+                Ok(())
+            })?;
+            manifest.record_file(directory, name)?;
+        }
+        manifest.save(directory)?;
         Ok(())
     }
-    fn download_zip_url(&self, nodoc: bool) -> String {
+    /// The location of the exported zip, relative to the MCPBot export
+    fn download_zip_path(&self, nodoc: bool) -> String {
         let docspec = if nodoc { "_nodoc" } else { "" };
         format!(
-            "http://export.mcpbot.bspk.rs/mcp_{channel}{docspec}/\
+            "mcp_{channel}{docspec}/\
             {value}-{minecraft_version}/mcp_{channel}{docspec}-{value}-{minecraft_version}.zip",
             channel = self.version.channel,
             docspec = docspec,
@@ -432,6 +791,150 @@ impl<'de> Deserialize<'de> for McpVersionSpec {
 #[fail(display = "Invalid MCP version spec {:?}", _0)]
 pub struct InvalidMcpVersionSpec(String);
 
+/// A MCP version which may need to be resolved against the version list for a minecraft version
+///
+/// Besides the exact specs like `snapshot_20180925`, this accepts `snapshot_latest`,
+/// dates like `snapshot_2018-09-25`, and `snapshot_before_20181001` for the latest version before a date.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct McpVersionRequest {
+    pub channel: McpChannel,
+    pub nodoc: bool,
+    pub selector: McpVersionSelector
+}
+impl McpVersionRequest {
+    /// The exact spec, if this doesn't need to be resolved
+    #[inline]
+    pub fn exact(&self) -> Option<McpVersionSpec> {
+        match self.selector {
+            McpVersionSelector::Exact(value) => Some(McpVersionSpec::new(value, self.channel, self.nodoc)),
+            McpVersionSelector::Latest | McpVersionSelector::Before(_) => None
+        }
+    }
+}
+impl From<McpVersionSpec> for McpVersionRequest {
+    #[inline]
+    fn from(spec: McpVersionSpec) -> McpVersionRequest {
+        McpVersionRequest {
+            channel: spec.version.channel,
+            nodoc: spec.nodoc,
+            selector: McpVersionSelector::Exact(spec.version.value)
+        }
+    }
+}
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum McpVersionSelector {
+    /// The exact value of the version, which is the date for snapshots
+    Exact(u32),
+    /// The latest version
+    Latest,
+    /// The latest version strictly before the specified value
+    Before(u32)
+}
+impl FromStr for McpVersionRequest {
+    type Err = InvalidMcpVersionSpec;
+
+    fn from_str(s: &str) -> Result<Self, InvalidMcpVersionSpec> {
+        let mut iter: iter::Peekable<_> = s.split('_').peekable();
+        let error = || InvalidMcpVersionSpec(s.into());
+        let channel = iter.next().ok_or_else(error)?
+            .parse::<McpChannel>().map_err(|_| error())?;
+        let nodoc = iter.peeking_next(|item| *item == "nodoc")
+            .is_some();
+        let selector = match iter.next().ok_or_else(error)? {
+            "latest" => McpVersionSelector::Latest,
+            "before" => {
+                let value = iter.next().ok_or_else(error)?;
+                McpVersionSelector::Before(parse_version_value(value).ok_or_else(error)?)
+            },
+            value => McpVersionSelector::Exact(parse_version_value(value).ok_or_else(error)?)
+        };
+        if iter.next().is_some() { return Err(error()) }
+        Ok(McpVersionRequest { channel, nodoc, selector })
+    }
+}
+/// Parse the value of a version, accepting dates like `2018-09-25` as well as `20180925`
+fn parse_version_value(s: &str) -> Option<u32> {
+    let bytes = s.as_bytes();
+    if bytes.len() == 10 && bytes[4] == b'-' && bytes[7] == b'-' {
+        format!("{}{}{}", &s[..4], &s[5..7], &s[8..]).parse().ok()
+    } else {
+        s.parse().ok()
+    }
+}
+impl Display for McpVersionRequest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.channel)?;
+        if self.nodoc {
+            f.write_str("_nodoc")?;
+        }
+        match self.selector {
+            McpVersionSelector::Exact(value) => write!(f, "_{}", value),
+            McpVersionSelector::Latest => f.write_str("_latest"),
+            McpVersionSelector::Before(value) => write!(f, "_before_{}", value),
+        }
+    }
+}
+impl Serialize for McpVersionRequest {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+        S: Serializer {
+        serializer.serialize_str(&format!("{}", self))
+    }
+}
+impl<'de> Deserialize<'de> for McpVersionRequest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+        D: Deserializer<'de> {
+        struct VersionRequestVisitor;
+        impl<'de> ::serde::de::Visitor<'de> for VersionRequestVisitor {
+            type Value = McpVersionRequest;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a McpVersionRequest")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<McpVersionRequest, E> where
+                E: de::Error, {
+                McpVersionRequest::from_str(v).map_err(de::Error::custom)
+            }
+        }
+        deserializer.deserialize_str(VersionRequestVisitor)
+    }
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Unable to resolve MCP version {} for minecraft {}", request, minecraft_version)]
+pub struct UnresolvedMcpVersion {
+    pub request: McpVersionRequest,
+    pub minecraft_version: MinecraftVersion
+}
+
 #[derive(Debug, Fail)]
 #[fail(display = "Invalid MCP channel")]
 pub struct InvalidMcpChannel;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn parse_srg_ids() {
+        assert_eq!(parse_method_id("func_12345_a"), Some(12345));
+        assert_eq!(parse_method_id("field_12345_a"), None);
+        assert_eq!(parse_param_id("p_12345_1_"), Some((12345, 1)));
+        assert_eq!(parse_param_id("p_i12345_1_"), None);
+    }
+    #[test]
+    fn resolve_requests() {
+        let versions: McpVersionList = ::serde_json::from_str(
+            r#"{"1.13": {"snapshot": [20180925, 20181001, 20180815], "stable": []}}"#
+        ).unwrap();
+        let minecraft_version = MinecraftVersion::release(1, 13, 0);
+        let resolve = |s: &str| versions.resolve(s.parse().unwrap(), minecraft_version)
+            .map(|version| version.value);
+        assert_eq!(resolve("snapshot_latest"), Some(20181001));
+        assert_eq!(resolve("snapshot_nodoc_before_20181001"), Some(20180925));
+        assert_eq!(resolve("snapshot_before_2018-08-01"), None);
+        assert_eq!(resolve("snapshot_2018-09-25"), Some(20180925));
+        assert_eq!(resolve("stable_latest"), None);
+        assert_eq!(format!("{}", "stable_nodoc_latest".parse::<McpVersionRequest>().unwrap()), "stable_nodoc_latest");
+        assert!("snapshot_latest_1".parse::<McpVersionRequest>().is_err());
+    }
+}
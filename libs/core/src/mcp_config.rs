@@ -0,0 +1,142 @@
+//! The extra data in the `mcp_config` artifact, which is published for 1.13 and later.
+//!
+//! Besides the srg mappings, MCPConfig specifies the srg ids of constructors,
+//! which methods are static, the exceptions each method throws,
+//! and the access transformers applied to the decompiled sources.
+//! All the names are srg names.
+use std::path::Path;
+use std::fs;
+
+use failure::Error;
+use failure_derive::Fail;
+use indexmap::IndexSet;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::MinecraftVersion;
+use crate::access::{AccessTransform, parse_access_transforms};
+
+/// The files we extract from the `config` directory of the `mcp_config` artifact
+pub(crate) const MCP_CONFIG_FILES: [&str; 4] = [
+    "constructors.txt",
+    "static_methods.txt",
+    "exceptions.txt",
+    "access.txt"
+];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct McpConfig {
+    pub constructors: Vec<ConstructorInfo>,
+    /// The srg names of all the static methods
+    pub static_methods: IndexSet<String>,
+    pub exceptions: Vec<MethodExceptions>,
+    pub access_transforms: Vec<AccessTransform>
+}
+impl McpConfig {
+    /// Load the config from the files we've extracted into the specified directory
+    pub(crate) fn load(directory: &Path) -> Result<McpConfig, Error> {
+        let read = |name: &str| fs::read_to_string(directory.join(name));
+        Ok(McpConfig {
+            constructors: parse_lines(&read("constructors.txt")?, ConstructorInfo::parse_line)?,
+            static_methods: data_lines(&read("static_methods.txt")?)
+                .map(String::from).collect(),
+            exceptions: parse_lines(&read("exceptions.txt")?, MethodExceptions::parse_line)?,
+            access_transforms: parse_access_transforms(&read("access.txt")?)?
+        })
+    }
+    #[inline]
+    pub fn is_static(&self, srg_name: &str) -> bool {
+        self.static_methods.contains(srg_name)
+    }
+    /// Find the srg id of the constructor with the specified class and descriptor
+    pub fn constructor_id(&self, class: &str, descriptor: &str) -> Option<u32> {
+        self.constructors.iter()
+            .find(|info| info.class == class && info.descriptor == descriptor)
+            .map(|info| info.id)
+    }
+}
+
+/// A constructor's srg id, which is used to name its parameters like `p_i1234_1_`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConstructorInfo {
+    pub id: u32,
+    pub class: String,
+    pub descriptor: String
+}
+impl ConstructorInfo {
+    /// Parse a line of the form `{id} {class} {descriptor}`
+    fn parse_line(line: &str) -> Result<ConstructorInfo, InvalidMcpConfig> {
+        let error = || InvalidMcpConfig(line.into());
+        let mut parts = line.split_whitespace();
+        let id = parts.next().and_then(|s| s.parse().ok()).ok_or_else(error)?;
+        let class = parts.next().ok_or_else(error)?.into();
+        let descriptor = parts.next().ok_or_else(error)?.into();
+        if parts.next().is_some() { return Err(error()) }
+        Ok(ConstructorInfo { id, class, descriptor })
+    }
+}
+
+/// The checked exceptions declared by a method
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MethodExceptions {
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+    pub exceptions: Vec<String>
+}
+impl MethodExceptions {
+    /// Parse a line of the form `{class}/{name} {descriptor} {exceptions...}`
+    fn parse_line(line: &str) -> Result<MethodExceptions, InvalidMcpConfig> {
+        let error = || InvalidMcpConfig(line.into());
+        let mut parts = line.split_whitespace();
+        let member = parts.next().ok_or_else(error)?;
+        let separator = member.rfind('/').ok_or_else(error)?;
+        let descriptor = parts.next().ok_or_else(error)?.into();
+        Ok(MethodExceptions {
+            class: member[..separator].into(),
+            name: member[(separator + 1)..].into(),
+            descriptor,
+            exceptions: parts.map(String::from).collect()
+        })
+    }
+}
+
+/// The lines of a config file, skipping blank lines and `#` comments
+fn data_lines<'a>(text: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+}
+fn parse_lines<T, F>(text: &str, func: F) -> Result<Vec<T>, InvalidMcpConfig>
+    where F: Fn(&str) -> Result<T, InvalidMcpConfig> {
+    data_lines(text).map(func).collect()
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid MCP config line {:?}", _0)]
+pub struct InvalidMcpConfig(String);
+
+/// Indicates the minecraft version predates the `mcp_config` artifact
+#[derive(Debug, Fail)]
+#[fail(display = "Minecraft {} doesn't have a MCP config", _0)]
+pub struct MissingMcpConfig(pub MinecraftVersion);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn parse_config_lines() {
+        assert_eq!(
+            ConstructorInfo::parse_line("1000 net/minecraft/util/math/BlockPos (III)V").unwrap(),
+            ConstructorInfo { id: 1000, class: "net/minecraft/util/math/BlockPos".into(), descriptor: "(III)V".into() }
+        );
+        assert_eq!(
+            MethodExceptions::parse_line("net/minecraft/world/World/func_72835_b ()V java/io/IOException").unwrap(),
+            MethodExceptions {
+                class: "net/minecraft/world/World".into(),
+                name: "func_72835_b".into(),
+                descriptor: "()V".into(),
+                exceptions: vec!["java/io/IOException".into()]
+            }
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Configures the upstream servers we fetch our data from.
+//!
+//! Each upstream source has an ordered list of mirrors,
+//! which are tried in order until one of them has the data we need.
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::fs::File;
+use std::io::BufReader;
+
+use failure::Error;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Upstream {
+    /// The forge maven repository, which contains the SRG data
+    ForgeMaven,
+    /// The MCPBot exports, which contain the MCP names
+    McpBotExport,
+    /// The spigot hub, which contains the spigot version info
+    SpigotHub,
+    /// The git repository containing spigot's BuildData
+    BuildData,
+    /// Mojang's launcher metadata, which contains the version manifest
+    MojangMeta,
+    /// Mojang's launcher downloads, which contain the official mappings
+    MojangLauncher,
+    /// The fabric maven repository, which contains the intermediary and yarn mappings
+    FabricMaven,
+}
+impl Upstream {
+    /// The official location of this upstream source
+    pub fn official_url(self) -> &'static str {
+        match self {
+            Upstream::ForgeMaven => "http://files.minecraftforge.net/maven",
+            Upstream::McpBotExport => "http://export.mcpbot.bspk.rs",
+            Upstream::SpigotHub => "https://hub.spigotmc.org",
+            Upstream::BuildData => "https://hub.spigotmc.org/stash/scm/spigot/builddata.git",
+            Upstream::MojangMeta => "https://launchermeta.mojang.com",
+            Upstream::MojangLauncher => "https://launcher.mojang.com",
+            Upstream::FabricMaven => "https://maven.fabricmc.net",
+        }
+    }
+}
+impl Display for Upstream {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Upstream::ForgeMaven => "forge maven",
+            Upstream::McpBotExport => "MCPBot export",
+            Upstream::SpigotHub => "spigot hub",
+            Upstream::BuildData => "BuildData",
+            Upstream::MojangMeta => "mojang launcher metadata",
+            Upstream::MojangLauncher => "mojang launcher",
+            Upstream::FabricMaven => "fabric maven",
+        })
+    }
+}
+
+/// The mirrors we use for each upstream source
+///
+/// Sources without any configured mirrors use their official location.
+/// This can be loaded from a JSON file of the form
+/// `{"mcp_bot_export": ["http://mirror.example.com/mcpbot", "http://export.mcpbot.bspk.rs"]}`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    forge_maven: Vec<String>,
+    mcp_bot_export: Vec<String>,
+    spigot_hub: Vec<String>,
+    build_data: Vec<String>,
+    mojang_meta: Vec<String>,
+    mojang_launcher: Vec<String>,
+    fabric_maven: Vec<String>
+}
+impl MirrorConfig {
+    #[inline]
+    pub fn new() -> MirrorConfig {
+        MirrorConfig::default()
+    }
+    pub fn load(location: &Path) -> Result<MirrorConfig, Error> {
+        Ok(::serde_json::from_reader(BufReader::new(File::open(location)?))?)
+    }
+    /// The base urls of the specified upstream source, in the order they should be tried
+    pub fn mirrors(&self, upstream: Upstream) -> Vec<&str> {
+        let mirrors = self.configured(upstream);
+        if mirrors.is_empty() {
+            vec![upstream.official_url()]
+        } else {
+            mirrors.iter().map(String::as_str).collect()
+        }
+    }
+    /// Replace the mirrors of the specified upstream source
+    #[inline]
+    pub fn set_mirrors(&mut self, upstream: Upstream, mirrors: Vec<String>) {
+        *self.configured_mut(upstream) = mirrors;
+    }
+    /// Try the specified mirror before any of the existing ones
+    pub fn prefer<U: Into<String>>(mut self, upstream: Upstream, url: U) -> MirrorConfig {
+        let mut mirrors: Vec<String> = self.mirrors(upstream).into_iter()
+            .map(String::from).collect();
+        mirrors.insert(0, url.into());
+        self.set_mirrors(upstream, mirrors);
+        self
+    }
+    fn configured(&self, upstream: Upstream) -> &Vec<String> {
+        match upstream {
+            Upstream::ForgeMaven => &self.forge_maven,
+            Upstream::McpBotExport => &self.mcp_bot_export,
+            Upstream::SpigotHub => &self.spigot_hub,
+            Upstream::BuildData => &self.build_data,
+            Upstream::MojangMeta => &self.mojang_meta,
+            Upstream::MojangLauncher => &self.mojang_launcher,
+            Upstream::FabricMaven => &self.fabric_maven,
+        }
+    }
+    fn configured_mut(&mut self, upstream: Upstream) -> &mut Vec<String> {
+        match upstream {
+            Upstream::ForgeMaven => &mut self.forge_maven,
+            Upstream::McpBotExport => &mut self.mcp_bot_export,
+            Upstream::SpigotHub => &mut self.spigot_hub,
+            Upstream::BuildData => &mut self.build_data,
+            Upstream::MojangMeta => &mut self.mojang_meta,
+            Upstream::MojangLauncher => &mut self.mojang_launcher,
+            Upstream::FabricMaven => &mut self.fabric_maven,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn prefer_mirror() {
+        let config = MirrorConfig::new()
+            .prefer(Upstream::McpBotExport, "http://mirror.example.com/mcpbot");
+        assert_eq!(
+            config.mirrors(Upstream::McpBotExport),
+            vec!["http://mirror.example.com/mcpbot", "http://export.mcpbot.bspk.rs"]
+        );
+        assert_eq!(config.mirrors(Upstream::SpigotHub), vec!["https://hub.spigotmc.org"]);
+    }
+    #[test]
+    fn parse_config() {
+        let config: MirrorConfig = ::serde_json::from_str(
+            r#"{"forge_maven": ["http://maven.example.com"]}"#
+        ).unwrap();
+        assert_eq!(config.mirrors(Upstream::ForgeMaven), vec!["http://maven.example.com"]);
+        assert_eq!(config.mirrors(Upstream::BuildData), vec![Upstream::BuildData.official_url()]);
+    }
+}
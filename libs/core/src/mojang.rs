@@ -0,0 +1,293 @@
+//! Mojang's official mappings, which are published in the ProGuard format.
+//!
+//! Each version has separate client and server mappings, which we merge into a single set.
+//! The files map the deobfuscated names into the obfuscated ones,
+//! so they need to be inverted before they line up with the other mapping systems.
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::Write;
+
+use indexmap::IndexMap;
+use failure::Error;
+use failure_derive::Fail;
+use serde_derive::Deserialize;
+use sha1::Sha1;
+use srglib::prelude::*;
+
+use crate::MinecraftVersion;
+use crate::utils::{LoadingCache, Downloader, write_atomic};
+use crate::manifest::CacheManifest;
+use crate::snapshot::load_srg_file;
+use crate::mirrors::Upstream;
+
+/// The files we cache for each version, which are the merged obf2mojang mappings
+pub(crate) const MOJANG_FILES: [&str; 1] = ["joined.srg"];
+/// The downloads of the version JSON that contain the mappings
+const MAPPING_DOWNLOADS: [&str; 2] = ["client_mappings", "server_mappings"];
+
+pub(crate) struct MojangMappingsCache {
+    cache_location: PathBuf,
+    versions: LoadingCache<IndexMap<MinecraftVersion, FrozenMappings>>,
+    downloader: Downloader
+}
+impl MojangMappingsCache {
+    pub fn setup(cache_location: PathBuf, downloader: Downloader) -> Result<MojangMappingsCache, Error> {
+        assert!(cache_location.exists());
+        Ok(MojangMappingsCache {
+            cache_location, versions: LoadingCache::default(), downloader
+        })
+    }
+    /// Load the mappings from the obfuscated names into mojang's names
+    pub fn load_mappings(&self, version: MinecraftVersion) -> Result<FrozenMappings, Error> {
+        self.versions.load(&version, || {
+            let version_directory = self.fetch_mappings(version)?;
+            load_srg_file(&version_directory, MOJANG_FILES[0])
+        })
+    }
+    /// Use the ProGuard mapping files at the specified locations for the version,
+    /// instead of downloading them from mojang.
+    ///
+    /// This replaces anything we've already cached for the version.
+    pub fn import_mappings(&self, version: MinecraftVersion, files: &[PathBuf]) -> Result<(), Error> {
+        let mut sources = Vec::with_capacity(files.len());
+        for location in files {
+            sources.push((format!("file:{}", location.display()), fs::read(location)?));
+        }
+        // Reloading means nobody can load the old mappings while we're writing the new ones
+        self.versions.reload(&version, || {
+            let version_directory = self.write_mappings(version, &sources)?;
+            load_srg_file(&version_directory, MOJANG_FILES[0])
+        })?;
+        Ok(())
+    }
+    /// Ensure the mappings for the specified version are cached, downloading them if needed
+    ///
+    /// Returns the directory containing the cached mappings.
+    fn fetch_mappings(&self, version: MinecraftVersion) -> Result<PathBuf, Error> {
+        let version_directory = self.version_directory(version);
+        if CacheManifest::check_valid(&version_directory, &MOJANG_FILES, &self.downloader)? {
+            return Ok(version_directory)
+        }
+        let info = self.load_version_info(version)?;
+        let mut sources = Vec::with_capacity(MAPPING_DOWNLOADS.len());
+        for name in &MAPPING_DOWNLOADS {
+            let download = info.downloads.get(*name)
+                .ok_or_else(|| MissingMojangMappings(version))?;
+            let data = self.downloader.download_url(Upstream::MojangLauncher, &download.url)?;
+            let mut hasher = Sha1::new();
+            hasher.update(&data);
+            let actual = hasher.digest().to_string();
+            if actual != download.sha1 {
+                return Err(CorruptedDownload { url: download.url.clone(), expected: download.sha1.clone(), actual }.into())
+            }
+            sources.push((download.url.clone(), data));
+        }
+        self.write_mappings(version, &sources)
+    }
+    /// Merge the specified ProGuard sources and write them into the cache
+    fn write_mappings(&self, version: MinecraftVersion, sources: &[(String, Vec<u8>)]) -> Result<PathBuf, Error> {
+        let version_directory = self.version_directory(version);
+        fs::create_dir_all(&version_directory)?;
+        let mut manifest = CacheManifest::new();
+        let mut builder = SimpleMappings::default();
+        for &(ref location, ref data) in sources {
+            manifest.record_source(location, data);
+            parse_proguard_into(&String::from_utf8_lossy(data), &mut builder)?;
+        }
+        let mojang2obf = builder.frozen();
+        write_atomic(&version_directory.join(MOJANG_FILES[0]), |writer| {
+            SrgMappingsFormat::write(&mojang2obf.inverted(), writer)?;
+            Ok(())
+        })?;
+        manifest.record_file(&version_directory, MOJANG_FILES[0])?;
+        manifest.save(&version_directory)?;
+        Ok(version_directory)
+    }
+    fn load_version_info(&self, version: MinecraftVersion) -> Result<VersionInfo, Error> {
+        let location = self.cache_location
+            .join(format!("version_info/{}.json", version));
+        fs::create_dir_all(location.parent().unwrap())?;
+        if !location.exists() {
+            // The manifest points us to the JSON for each version
+            let manifest: VersionManifest = ::serde_json::from_slice(&self.downloader.download_buffer(
+                Upstream::MojangMeta,
+                "mc/game/version_manifest.json"
+            )?)?;
+            // Older pre-releases are named like `1.14 Pre-Release 1`, so we need to compare them parsed
+            let entry = manifest.versions.iter()
+                .find(|entry| entry.id.parse::<MinecraftVersion>().ok() == Some(version))
+                .ok_or_else(|| version.unknown())?;
+            let buffer = self.downloader.download_url(Upstream::MojangMeta, &entry.url)?;
+            write_atomic(&location, |writer| {
+                writer.write_all(&buffer)?;
+                Ok(())
+            })?;
+        }
+        Ok(::serde_json::from_slice(&fs::read(&location)?)?)
+    }
+    #[inline]
+    fn version_directory(&self, version: MinecraftVersion) -> PathBuf {
+        self.cache_location.join(format!("versions/{}", version))
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionManifest {
+    versions: Vec<VersionManifestEntry>
+}
+#[derive(Deserialize)]
+struct VersionManifestEntry {
+    id: String,
+    url: String
+}
+#[derive(Deserialize)]
+struct VersionInfo {
+    #[serde(default)]
+    downloads: IndexMap<String, VersionDownload>
+}
+#[derive(Deserialize)]
+struct VersionDownload {
+    sha1: String,
+    url: String
+}
+
+/// Parse ProGuard mappings, which go from the deobfuscated names into the obfuscated ones
+pub fn parse_proguard(text: &str) -> Result<FrozenMappings, InvalidProguardMapping> {
+    let mut builder = SimpleMappings::default();
+    parse_proguard_into(text, &mut builder)?;
+    Ok(builder.frozen())
+}
+fn parse_proguard_into(text: &str, builder: &mut SimpleMappings) -> Result<(), InvalidProguardMapping> {
+    let mut current_class: Option<ReferenceType> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') { continue }
+        let error = || InvalidProguardMapping(line.into());
+        let separator = trimmed.find(" -> ").ok_or_else(error)?;
+        let (original, renamed) = (&trimmed[..separator], &trimmed[(separator + 4)..]);
+        if !line.starts_with(char::is_whitespace) {
+            // Classes are of the form `net.minecraft.server.MinecraftServer -> abc:`
+            if !renamed.ends_with(':') { return Err(error()) }
+            let original = ReferenceType::from_internal_name(&original.replace('.', "/"));
+            let renamed = renamed[..(renamed.len() - 1)].replace('.', "/");
+            builder.set_remapped_class(original.clone(), ReferenceType::from_internal_name(&renamed));
+            current_class = Some(original);
+            continue
+        }
+        let declaring_type = current_class.clone().ok_or_else(error)?;
+        // Methods may be prefixed with their line numbers, like `12:15:void tick() -> a`
+        let original = original.trim_start_matches(|c: char| c.is_ascii_digit() || c == ':');
+        let space = original.find(' ').ok_or_else(error)?;
+        let (member_type, member) = (&original[..space], &original[(space + 1)..]);
+        match member.find('(') {
+            Some(open) => {
+                let close = member.rfind(')').ok_or_else(error)?;
+                let name = &member[..open];
+                // Methods inlined from other classes are qualified, and don't belong to this class
+                if name.contains('.') || name == renamed { continue }
+                let mut descriptor = String::from("(");
+                for parameter in member[(open + 1)..close].split(',').filter(|s| !s.is_empty()) {
+                    push_type_descriptor(&mut descriptor, parameter);
+                }
+                descriptor.push(')');
+                push_type_descriptor(&mut descriptor, member_type);
+                builder.set_method_name(
+                    MethodData::new(name.into(), declaring_type, MethodSignature::from_descriptor(&descriptor)),
+                    renamed.into()
+                );
+            },
+            None => {
+                if member == renamed { continue }
+                builder.set_field_name(FieldData::new(member.into(), declaring_type), renamed.into());
+            }
+        }
+    }
+    Ok(())
+}
+/// Append the descriptor of a java source type like `int[]` or `java.lang.String`
+fn push_type_descriptor(descriptor: &mut String, java_type: &str) {
+    let mut element_type = java_type.trim();
+    while element_type.ends_with("[]") {
+        descriptor.push('[');
+        element_type = &element_type[..(element_type.len() - 2)];
+    }
+    descriptor.push_str(match element_type {
+        "boolean" => "Z",
+        "byte" => "B",
+        "char" => "C",
+        "short" => "S",
+        "int" => "I",
+        "long" => "J",
+        "float" => "F",
+        "double" => "D",
+        "void" => "V",
+        _ => {
+            descriptor.push('L');
+            descriptor.push_str(&element_type.replace('.', "/"));
+            ";"
+        }
+    });
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid ProGuard mapping {:?}", _0)]
+pub struct InvalidProguardMapping(String);
+/// Indicates mojang didn't publish mappings for the version, which is the case before 1.14.4
+#[derive(Debug, Fail)]
+#[fail(display = "Mojang didn't publish mappings for minecraft {}", _0)]
+pub struct MissingMojangMappings(pub MinecraftVersion);
+#[derive(Debug, Fail)]
+#[fail(display = "Downloaded {} with sha1 {}, but expected {}", url, actual, expected)]
+pub struct CorruptedDownload {
+    pub url: String,
+    pub expected: String,
+    pub actual: String
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn type_descriptors() {
+        let mut descriptor = String::new();
+        push_type_descriptor(&mut descriptor, "int");
+        push_type_descriptor(&mut descriptor, "java.lang.String[][]");
+        push_type_descriptor(&mut descriptor, "net.minecraft.world.level.Level");
+        assert_eq!(descriptor, "I[[Ljava/lang/String;Lnet/minecraft/world/level/Level;");
+    }
+    #[test]
+    fn proguard_mappings() {
+        let mojang2obf = parse_proguard(
+            "# compiler: R8\n\
+            net.minecraft.server.MinecraftServer -> abc:\n\
+            \x20   java.lang.String motd -> a\n\
+            \x20   int port -> port\n\
+            \x20   12:15:void tick(int,net.minecraft.server.MinecraftServer) -> b\n\
+            \x20   16:16:int net.minecraft.util.Mth.floor(double):30:30 -> b\n"
+        ).unwrap();
+        let server = ReferenceType::from_internal_name("net/minecraft/server/MinecraftServer");
+        let obf_server = ReferenceType::from_internal_name("abc");
+        assert_eq!(&*mojang2obf.get_remapped_class(&server).unwrap().internal_name(), "abc");
+        let motd = FieldData::new("motd".into(), server.clone());
+        assert_eq!(&*mojang2obf.get_remapped_field(&motd).unwrap().name, "a");
+        // Members that aren't obfuscated don't need a mapping
+        assert_eq!(mojang2obf.fields().count(), 1);
+        let tick = MethodData::new(
+            "tick".into(),
+            server.clone(),
+            MethodSignature::from_descriptor("(ILnet/minecraft/server/MinecraftServer;)V")
+        );
+        assert_eq!(&*mojang2obf.get_remapped_method(&tick).unwrap().name, "b");
+        // The inlined method belongs to Mth, not MinecraftServer
+        assert_eq!(mojang2obf.methods().count(), 1);
+        let obf2mojang = mojang2obf.inverted();
+        assert_eq!(
+            &*obf2mojang.get_remapped_class(&obf_server).unwrap().internal_name(),
+            "net/minecraft/server/MinecraftServer"
+        );
+        let obf_motd = FieldData::new("a".into(), obf_server.clone());
+        assert_eq!(&*obf2mojang.get_remapped_field(&obf_motd).unwrap().name, "motd");
+        let obf_tick = MethodData::new("b".into(), obf_server, MethodSignature::from_descriptor("(ILabc;)V"));
+        assert_eq!(&*obf2mojang.get_remapped_method(&obf_tick).unwrap().name, "tick");
+    }
+}
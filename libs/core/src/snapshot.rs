@@ -0,0 +1,221 @@
+//! A compact binary snapshot of parsed mappings.
+//!
+//! Parsing the text SRG files dominates the startup time of short-lived processes like the CLI,
+//! so we write a snapshot next to each cached SRG file and load it in preference to the text.
+//!
+//! Every string is interned into a single table, and the entries just reference it by index.
+//! The header records the hash of the source file (taken from the cache manifest),
+//! so the snapshot is automatically ignored once the source changes.
+use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::str;
+
+use failure::Error;
+use failure_derive::Fail;
+use indexmap::IndexSet;
+use srglib::prelude::*;
+
+use crate::manifest::CacheManifest;
+use crate::utils::write_atomic;
+
+const MAGIC: &[u8; 8] = b"MMSNAP\0\0";
+/// The current version of the snapshot format, which must be incremented on any change
+const FORMAT_VERSION: u32 = 1;
+
+/// Load the specified SRG file from a cache directory, preferring its snapshot if it's up to date
+///
+/// Files with the `.tsrg` extension are parsed as TSRG.
+pub(crate) fn load_srg_file(directory: &Path, name: &str) -> Result<FrozenMappings, Error> {
+    let fingerprint = CacheManifest::load(directory)
+        .and_then(|manifest| manifest.files.get(name).map(|info| info.sha1.clone()));
+    let snapshot_file = directory.join(format!("{}.snapshot", name));
+    if let Some(ref fingerprint) = fingerprint {
+        // The snapshot is just an optimization, so if it's unreadable or corrupted
+        // we just fall back to the text
+        if let Ok(data) = fs::read(&snapshot_file) {
+            if let Ok(Some(mappings)) = read_snapshot(&data, fingerprint) {
+                return Ok(mappings)
+            }
+        }
+    }
+    let source = directory.join(name);
+    let reader = BufReader::new(File::open(&source)?);
+    let mappings = if name.ends_with(".tsrg") {
+        TabSrgMappingsFormat::parse_stream(reader)?
+    } else {
+        SrgMappingsFormat::parse_stream(reader)?
+    };
+    if let Some(ref fingerprint) = fingerprint {
+        // Failing to write the snapshot (say on a read-only or full volume)
+        // only means we'll have to parse the text again next time
+        let _ = write_atomic(&snapshot_file, |writer| {
+            writer.write_all(&write_snapshot(&mappings, fingerprint))?;
+            Ok(())
+        });
+    }
+    Ok(mappings)
+}
+
+/// Serialize the mappings into a snapshot, tagged with the fingerprint of their source
+pub fn write_snapshot(mappings: &FrozenMappings, fingerprint: &str) -> Vec<u8> {
+    let mut strings = IndexSet::new();
+    let mut classes = Vec::new();
+    for (original, renamed) in mappings.classes() {
+        classes.push(intern(&mut strings, &*original.internal_name()));
+        classes.push(intern(&mut strings, &*renamed.internal_name()));
+    }
+    let mut fields = Vec::new();
+    for (original, renamed) in mappings.fields() {
+        fields.push(intern(&mut strings, &*original.declaring_type().internal_name()));
+        fields.push(intern(&mut strings, &original.name));
+        fields.push(intern(&mut strings, &renamed.name));
+    }
+    let mut methods = Vec::new();
+    for (original, renamed) in mappings.methods() {
+        methods.push(intern(&mut strings, &*original.declaring_type().internal_name()));
+        methods.push(intern(&mut strings, &original.name));
+        methods.push(intern(&mut strings, &*original.signature().descriptor()));
+        methods.push(intern(&mut strings, &renamed.name));
+    }
+    let mut output = Vec::with_capacity(64 * 1024);
+    output.extend_from_slice(MAGIC);
+    write_u32(&mut output, FORMAT_VERSION);
+    write_str(&mut output, fingerprint);
+    write_u32(&mut output, strings.len() as u32);
+    for s in &strings {
+        write_str(&mut output, s);
+    }
+    write_u32(&mut output, (classes.len() / 2) as u32);
+    write_u32(&mut output, (fields.len() / 3) as u32);
+    write_u32(&mut output, (methods.len() / 4) as u32);
+    for &index in classes.iter().chain(&fields).chain(&methods) {
+        write_u32(&mut output, index);
+    }
+    output
+}
+
+/// Deserialize the mappings from a snapshot,
+/// returning `None` if it's from an old format or a different source.
+pub fn read_snapshot(data: &[u8], fingerprint: &str) -> Result<Option<FrozenMappings>, Error> {
+    let mut reader = SnapshotReader { data, offset: 0 };
+    if reader.read_bytes(MAGIC.len())? != MAGIC {
+        return Err(InvalidSnapshot("Invalid magic").into())
+    }
+    if reader.read_u32()? != FORMAT_VERSION || reader.read_str()? != fingerprint {
+        return Ok(None)
+    }
+    let num_strings = reader.read_u32()? as usize;
+    let mut strings = Vec::with_capacity(num_strings);
+    for _ in 0..num_strings {
+        strings.push(reader.read_str()?);
+    }
+    let num_classes = reader.read_u32()?;
+    let num_fields = reader.read_u32()?;
+    let num_methods = reader.read_u32()?;
+    let mut builder = SimpleMappings::default();
+    for _ in 0..num_classes {
+        let original = reader.read_string_ref(&strings)?;
+        let renamed = reader.read_string_ref(&strings)?;
+        builder.set_remapped_class(
+            ReferenceType::from_internal_name(original),
+            ReferenceType::from_internal_name(renamed)
+        );
+    }
+    for _ in 0..num_fields {
+        let declaring_type = ReferenceType::from_internal_name(reader.read_string_ref(&strings)?);
+        let name = reader.read_string_ref(&strings)?;
+        let renamed = reader.read_string_ref(&strings)?;
+        builder.set_field_name(FieldData::new(name.into(), declaring_type), renamed.into());
+    }
+    for _ in 0..num_methods {
+        let declaring_type = ReferenceType::from_internal_name(reader.read_string_ref(&strings)?);
+        let name = reader.read_string_ref(&strings)?;
+        let signature = MethodSignature::from_descriptor(reader.read_string_ref(&strings)?);
+        let renamed = reader.read_string_ref(&strings)?;
+        builder.set_method_name(MethodData::new(name.into(), declaring_type, signature), renamed.into());
+    }
+    if reader.offset != data.len() {
+        return Err(InvalidSnapshot("Trailing data").into())
+    }
+    Ok(Some(builder.frozen()))
+}
+
+#[inline]
+fn intern(strings: &mut IndexSet<String>, s: &str) -> u32 {
+    if let Some((index, _)) = strings.get_full(s) {
+        return index as u32
+    }
+    strings.insert(s.into());
+    (strings.len() - 1) as u32
+}
+#[inline]
+fn write_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&[
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8
+    ]);
+}
+#[inline]
+fn write_str(output: &mut Vec<u8>, s: &str) {
+    write_u32(output, s.len() as u32);
+    output.extend_from_slice(s.as_bytes());
+}
+
+struct SnapshotReader<'a> {
+    data: &'a [u8],
+    offset: usize
+}
+impl<'a> SnapshotReader<'a> {
+    #[inline]
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], InvalidSnapshot> {
+        if self.data.len() - self.offset < len {
+            return Err(InvalidSnapshot("Unexpected end of snapshot"))
+        }
+        let result = &self.data[self.offset..(self.offset + len)];
+        self.offset += len;
+        Ok(result)
+    }
+    #[inline]
+    fn read_u32(&mut self) -> Result<u32, InvalidSnapshot> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from(bytes[0])
+            | (u32::from(bytes[1]) << 8)
+            | (u32::from(bytes[2]) << 16)
+            | (u32::from(bytes[3]) << 24))
+    }
+    #[inline]
+    fn read_str(&mut self) -> Result<&'a str, InvalidSnapshot> {
+        let len = self.read_u32()? as usize;
+        str::from_utf8(self.read_bytes(len)?)
+            .map_err(|_| InvalidSnapshot("Invalid UTF8"))
+    }
+    #[inline]
+    fn read_string_ref<'s>(&mut self, strings: &[&'s str]) -> Result<&'s str, InvalidSnapshot> {
+        let index = self.read_u32()? as usize;
+        strings.get(index).cloned()
+            .ok_or(InvalidSnapshot("Invalid string index"))
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid mappings snapshot: {}", _0)]
+pub struct InvalidSnapshot(&'static str);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn roundtrip() {
+        let text = "CL: a net/minecraft/server/MinecraftServer\n\
+            FD: a/b net/minecraft/server/MinecraftServer/field_1234_b\n\
+            MD: a/c (La;)V net/minecraft/server/MinecraftServer/func_5678_c (Lnet/minecraft/server/MinecraftServer;)V\n";
+        let mappings = SrgMappingsFormat::parse_text(text).unwrap();
+        let data = write_snapshot(&mappings, "fingerprint");
+        let loaded = read_snapshot(&data, "fingerprint").unwrap().unwrap();
+        assert_eq!(SrgMappingsFormat::write_string(&loaded), SrgMappingsFormat::write_string(&mappings));
+        assert!(read_snapshot(&data, "changed").unwrap().is_none());
+    }
+}
@@ -1,136 +1,592 @@
-use std::io::{Read, BufReader, Write, Cursor};
+use std::io::{Read, Write, Cursor, BufReader};
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use indexmap::IndexMap;
 use failure::Error;
 use git2::{Repository, Commit, Oid};
 use srglib::prelude::*;
-use crossbeam::atomic::ArcCell;
 use parking_lot::Mutex;
+use serde::ser::{Serialize, Serializer};
+use serde::de::{self, Deserialize, Deserializer};
 use serde_derive::Deserialize;
+use failure_derive::Fail;
 
 use crate::MinecraftVersion;
-use crate::utils::load_from_commit;
+use crate::utils::{load_from_commit, LoadingCache, Downloader, write_atomic};
+use crate::manifest::CacheManifest;
+use crate::snapshot::load_srg_file;
+use crate::mirrors::Upstream;
+use crate::access::{AccessTransform, parse_access_transforms};
 
-fn transform_spigot_packages(s: &str) -> Option<String> {
-    if s.is_empty() { Some("net/minecraft/server".into()) } else { None }
-}
+/// The files we cache for each revision, in the order class, members, chained,
+/// access transforms, packages and BuildData info
+pub(crate) const MAPPING_FILES: [&str; 6] = [
+    "class.srg",
+    "members.srg",
+    "chained.srg",
+    "access.at",
+    "package.srg",
+    "info.json"
+];
+/// The package spigot's chained mappings place the server classes in
+pub const SERVER_PACKAGE: &str = "net/minecraft/server";
 
 pub(crate) struct SpigotMappingsCache {
     cache_location: PathBuf,
     // NOTE: Since spigot has significantly fewer versions, we don't need have LRU eviction
-    versions: ArcCell<IndexMap<MinecraftVersion, Arc<SpigotMappings>>>,
-    lock: Mutex<()>,
+    /// The latest revision of each minecraft version
+    versions: LoadingCache<IndexMap<MinecraftVersion, Arc<SpigotMappings>>>,
+    /// All the revisions we've loaded, keyed by their BuildData commit
+    revisions: LoadingCache<IndexMap<Oid, Arc<SpigotMappings>>>,
+    /// Guards the BuildData repository, which we can't safely update concurrently
+    build_data_lock: Mutex<()>,
+    downloader: Downloader
 }
 impl SpigotMappingsCache {
-    pub fn setup(cache_location: PathBuf) -> Result<SpigotMappingsCache, Error> {
+    pub fn setup(cache_location: PathBuf, downloader: Downloader) -> Result<SpigotMappingsCache, Error> {
         assert!(cache_location.exists());
-        Ok(SpigotMappingsCache { cache_location, versions: ArcCell::default(), lock: Mutex::new(()) })
+        Ok(SpigotMappingsCache {
+            cache_location, versions: LoadingCache::default(),
+            revisions: LoadingCache::default(),
+            build_data_lock: Mutex::new(()),
+            downloader
+        })
     }
+    /// Load the latest revision of the mappings for the specified version
     pub fn load_mappings(&self, version: MinecraftVersion) -> Result<Arc<SpigotMappings>, Error> {
-        if let Some(loaded) = self.versions.get().get(&version) {
-            return Ok(loaded.clone());
+        // Resolving the latest revision needs the network, so it's shared too
+        self.versions.load(&version, || self.load_revision(&SpigotRevision::Latest(version)))
+    }
+    pub fn load_revision(&self, revision: &SpigotRevision) -> Result<Arc<SpigotMappings>, Error> {
+        let refs = self.resolve_revision(revision)?;
+        self.load_resolved(&refs)
+    }
+    fn load_resolved(&self, refs: &RevisionRefs) -> Result<Arc<SpigotMappings>, Error> {
+        self.revisions.load(&refs.build_data, || {
+            let revision_directory = self.fetch_mappings(refs)?;
+            let class_mappings = load_srg_file(&revision_directory, MAPPING_FILES[0])?;
+            let member_mappings = load_srg_file(&revision_directory, MAPPING_FILES[1])?;
+            let chained_mappings = load_srg_file(&revision_directory, MAPPING_FILES[2])?;
+            let access_transforms = parse_access_transforms(
+                &fs::read_to_string(revision_directory.join(MAPPING_FILES[3]))?
+            )?;
+            let package_mappings = PackageMappings::parse(
+                &fs::read_to_string(revision_directory.join(MAPPING_FILES[4]))?
+            )?;
+            let info = BuildDataInfo::read(&mut File::open(revision_directory.join(MAPPING_FILES[5]))?)?;
+            Ok(Arc::new(SpigotMappings {
+                minecraft_version: info.minecraft_version.parse()?,
+                minecraft_hash: info.minecraft_hash.to_ascii_lowercase(),
+                build_data_commit: refs.build_data.to_string(),
+                class_mappings, member_mappings, chained_mappings,
+                access_transforms, package_mappings
+            }))
+        })
+    }
+    /// Load the versioned package CraftBukkit relocates the server classes into for the specified revision,
+    /// like `net/minecraft/server/v1_13_R2`
+    ///
+    /// This is declared in CraftBukkit's pom, so it's only downloaded the first time it's needed.
+    pub fn load_craftbukkit_package(&self, revision: &SpigotRevision) -> Result<String, Error> {
+        let refs = self.resolve_revision(revision)?;
+        let location = self.cache_location
+            .join(format!("craftbukkit/{}.txt", refs.build_data));
+        if location.exists() {
+            return Ok(fs::read_to_string(&location)?.trim().to_string())
         }
-        self.load_mappings_fallback(version)
+        fs::create_dir_all(location.parent().unwrap())?;
+        let craft_bukkit = match refs.craft_bukkit {
+            Some(ref craft_bukkit) => craft_bukkit.clone(),
+            None => {
+                /*
+                 * We don't know which CraftBukkit build used this revision,
+                 * so we use the latest one for the same minecraft version.
+                 * The versioned package only changes along with the minecraft version.
+                 */
+                let version = self.load_resolved(&refs)?.minecraft_version;
+                self.load_version_info(&format!("{}", version), || version.unknown().into())?
+                    .refs.craft_bukkit
+            }
+        };
+        let pom_path = format!(
+            "stash/projects/SPIGOT/repos/craftbukkit/raw/pom.xml?at={}",
+            craft_bukkit
+        );
+        let pom = self.downloader.download_buffer(Upstream::SpigotHub, &pom_path)?;
+        let craftbukkit_package = parse_craftbukkit_package(&String::from_utf8_lossy(&pom))
+            .ok_or_else(|| MissingCraftBukkitPackage(craft_bukkit.clone()))?;
+        write_atomic(&location, |writer| {
+            writer.write_all(craftbukkit_package.as_bytes())?;
+            Ok(())
+        })?;
+        Ok(craftbukkit_package)
     }
-    #[cold]
-    fn load_mappings_fallback(&self, version: MinecraftVersion) -> Result<Arc<SpigotMappings>, Error> {
-        // This lock guarantees that only one person will be loading versions at a time
-        let _guard = self.lock.lock();
-        let versions = self.versions.get();
-        /*
-         * Now that we have the lock,
-         * let's check again to se if our version is present.
-         * Someone else could've already loaded it while we were blocking
-         */
-        if let Some(loaded) = versions.get(&version) {
-            return Ok(loaded.clone());
-        }
-        let info = self.load_version_info(version)?;
-        let mut updated_versions =
-            (*versions).clone();
-        drop(versions); // We're invalidating this
-        let version_directory = self.cache_location
-            .join(format!("versions/{}", version));
-        fs::create_dir_all(&version_directory)?;
-        let class_file = version_directory.join("class.srg");
-        let members_file = version_directory.join("members.srg");
-        let combined_file = version_directory.join("chained.srg");
-        if !class_file.exists() || !members_file.exists() || !combined_file.exists() {
-            let build_data = self.fetch_build_data(&info.refs.build_data)?;
-            let oid = Oid::from_str(&info.refs.build_data)?;
-            let commit = build_data.find_commit(oid)?;
-            let class_mappings = commit.read_class_mappings()?;
-            let member_mappings = commit.read_member_mappings()?;
-            let chained = class_mappings.clone().chain(member_mappings.clone())
-                .transform_packages(transform_spigot_packages);
-            SrgMappingsFormat::write(&class_mappings, File::create(&class_file)?)?;
-            SrgMappingsFormat::write(&member_mappings, File::create(&members_file)?)?;
-            SrgMappingsFormat::write(&chained, File::create(&combined_file)?)?;
-        }
-        let class_mappings = SrgMappingsFormat::parse_stream(BufReader::new(File::open(&class_file)?))?;
-        let member_mappings = SrgMappingsFormat::parse_stream(BufReader::new(File::open(&members_file)?))?;
-        let chained_mappings = SrgMappingsFormat::parse_stream(BufReader::new(File::open(&combined_file)?))?;
-        let mappings = Arc::new(SpigotMappings { class_mappings, member_mappings, chained_mappings });
-        updated_versions.insert(version, mappings.clone());
-        self.versions.set(Arc::new(updated_versions));
-        Ok(mappings)
-    }
-    fn load_version_info(&self, version: MinecraftVersion) -> Result<VersionInfo, Error> {
+    /// Ensure the latest mappings for the specified version are cached, without loading them
+    pub fn fetch_version(&self, version: MinecraftVersion) -> Result<(), Error> {
+        let refs = self.resolve_revision(&SpigotRevision::Latest(version))?;
+        self.fetch_mappings(&refs)?;
+        Ok(())
+    }
+    /// List all the minecraft versions spigot has published version info for
+    pub fn known_versions(&self) -> Result<Vec<MinecraftVersion>, Error> {
+        let listing = self.downloader.download_buffer(Upstream::SpigotHub, "versions/")?;
+        let listing = String::from_utf8_lossy(&listing);
+        let mut versions = Vec::new();
+        // The listing is a simple HTML index, with a link to each version's JSON
+        for part in listing.split("href=\"").skip(1) {
+            let link = match part.find('"') {
+                Some(end) => &part[..end],
+                None => continue
+            };
+            if link.ends_with(".json") {
+                if let Ok(version) = link[..(link.len() - 5)].parse::<MinecraftVersion>() {
+                    versions.push(version);
+                }
+            }
+        }
+        versions.sort();
+        versions.dedup();
+        Ok(versions)
+    }
+    /// List the revisions of spigot's mappings in BuildData, from newest to oldest
+    ///
+    /// Only the commits that actually changed the mappings are included,
+    /// optionally restricted to a single minecraft version.
+    /// When we're online, this updates BuildData first.
+    pub fn known_revisions(&self, version: Option<MinecraftVersion>) -> Result<Vec<SpigotRevisionInfo>, Error> {
+        let _guard = self.build_data_lock.lock();
+        let build_data = self.update_build_data()?;
+        build_data.list_revisions(version)
+    }
+    /// Find the revisions that were made for the server jar at the specified location,
+    /// by comparing its hash against BuildData.
+    pub fn identify_server_jar(&self, location: &Path) -> Result<Vec<SpigotRevisionInfo>, Error> {
+        let hash = md5_file(location)?;
+        Ok(self.known_revisions(None)?.into_iter()
+            .filter(|revision| revision.minecraft_hash == hash)
+            .collect())
+    }
+    /// Check the server jar at the specified location is the one the revision was made for,
+    /// failing with `MismatchedServerJar` if it isn't.
+    pub fn verify_server_jar(&self, revision: &SpigotRevision, location: &Path) -> Result<(), Error> {
+        let mappings = self.load_revision(revision)?;
+        let actual = md5_file(location)?;
+        if actual != mappings.minecraft_hash {
+            return Err(MismatchedServerJar {
+                revision: revision.clone(),
+                minecraft_version: mappings.minecraft_version,
+                expected: mappings.minecraft_hash.clone(),
+                actual
+            }.into())
+        }
+        Ok(())
+    }
+    /// Determine the BuildData commit (and CraftBukkit ref if known) of the specified revision
+    fn resolve_revision(&self, revision: &SpigotRevision) -> Result<RevisionRefs, Error> {
+        let info = match *revision {
+            SpigotRevision::Latest(version) => {
+                self.load_version_info(&format!("{}", version), || version.unknown().into())?
+            },
+            SpigotRevision::Build(build) => {
+                self.load_version_info(&format!("{}", build), || UnknownSpigotRevision(revision.clone()).into())?
+            },
+            SpigotRevision::Commit(ref hash) => {
+                // We don't know what CraftBukkit build a bare commit was used with
+                return Ok(RevisionRefs { build_data: self.resolve_commit(hash)?, craft_bukkit: None })
+            }
+        };
+        Ok(RevisionRefs {
+            build_data: Oid::from_str(&info.refs.build_data)?,
+            craft_bukkit: Some(info.refs.craft_bukkit)
+        })
+    }
+    /// Resolve a possibly abbreviated BuildData commit, updating BuildData if we don't know it yet
+    fn resolve_commit(&self, hash: &str) -> Result<Oid, Error> {
+        if hash.len() == 40 {
+            return Ok(Oid::from_str(hash)?)
+        }
+        let _guard = self.build_data_lock.lock();
+        let repo_location = self.cache_location.join("BuildData");
+        if repo_location.exists() {
+            let repo = Repository::open(&repo_location)?;
+            if let Ok(object) = repo.revparse_single(hash) {
+                return Ok(repo.find_commit(object.id())?.id())
+            }
+        }
+        let build_data = self.update_build_data()?;
+        let object = build_data.0.revparse_single(hash)
+            .map_err(|_| UnknownSpigotRevision(SpigotRevision::Commit(hash.into())))?;
+        Ok(build_data.0.find_commit(object.id())?.id())
+    }
+    /// Ensure the mappings for the specified revision are cached, extracting them from BuildData if needed
+    ///
+    /// Returns the directory containing the cached mappings.
+    fn fetch_mappings(&self, refs: &RevisionRefs) -> Result<PathBuf, Error> {
+        let revision_directory = self.cache_location
+            .join(format!("revisions/{}", refs.build_data));
+        if CacheManifest::check_valid(&revision_directory, &MAPPING_FILES, &self.downloader)? {
+            return Ok(revision_directory)
+        }
+        let _guard = self.build_data_lock.lock();
+        // Someone else could've extracted it while we were waiting for BuildData
+        if CacheManifest::check_valid(&revision_directory, &MAPPING_FILES, &self.downloader)? {
+            return Ok(revision_directory)
+        }
+        fs::create_dir_all(&revision_directory)?;
+        let build_data = self.fetch_build_data(refs.build_data)?;
+        let commit = build_data.find_commit(refs.build_data)?;
+        let mut manifest = CacheManifest::new();
+        commit.record_sources(&mut manifest)?;
+        let class_mappings = commit.read_class_mappings()?;
+        let member_mappings = commit.read_member_mappings()?;
+        let package_data = commit.read_package_mappings()?;
+        let package_mappings = PackageMappings::parse(&package_data)?;
+        let chained = package_mappings.apply(class_mappings.clone().chain(member_mappings.clone()));
+        for (name, mappings) in MAPPING_FILES.iter().zip(&[class_mappings, member_mappings, chained]) {
+            write_atomic(&revision_directory.join(name), |writer| {
+                SrgMappingsFormat::write(mappings, writer)?;
+                Ok(())
+            })?;
+            manifest.record_file(&revision_directory, name)?;
+        }
+        // We keep the access transforms in spigot's format, since we need to parse them anyways
+        let access_transforms = commit.read_access_transforms()?;
+        write_atomic(&revision_directory.join(MAPPING_FILES[3]), |writer| {
+            writer.write_all(access_transforms.as_bytes())?;
+            Ok(())
+        })?;
+        manifest.record_file(&revision_directory, MAPPING_FILES[3])?;
+        write_atomic(&revision_directory.join(MAPPING_FILES[4]), |writer| {
+            writer.write_all(package_data.as_bytes())?;
+            Ok(())
+        })?;
+        manifest.record_file(&revision_directory, MAPPING_FILES[4])?;
+        // The info tells us which minecraft version the revision is for
+        let info = commit.read_info()?;
+        write_atomic(&revision_directory.join(MAPPING_FILES[5]), |writer| {
+            writer.write_all(info.as_bytes())?;
+            Ok(())
+        })?;
+        manifest.record_file(&revision_directory, MAPPING_FILES[5])?;
+        manifest.save(&revision_directory)?;
+        Ok(revision_directory)
+    }
+    /// Load spigot's version info for a minecraft version or build number
+    ///
+    /// If spigot doesn't know about it, we fail with the error given by `unknown`.
+    fn load_version_info<F>(&self, name: &str, unknown: F) -> Result<VersionInfo, Error>
+        where F: FnOnce() -> Error {
         let location = self.cache_location
-            .join(format!("version_info/{}.json", version));
+            .join(format!("version_info/{}.json", name));
         fs::create_dir_all(location.parent().unwrap())?;
         if !location.exists() {
             // If we don't have it locally we need to check spigot
-            let url = format!("https://hub.spigotmc.org/versions/{}.json", version);
-            let buffer = match crate::utils::download_buffer(&url) {
-                Err(ref e) if e.downcast_ref::<crate::utils::HttpNotFound>().is_some() => {
-                    // If it's a 404, then we know it's an unknown version
-                    return Err(version.unknown().into())
+            let path = format!("versions/{}.json", name);
+            let buffer = match self.downloader.download_buffer(Upstream::SpigotHub, &path) {
+                Err(ref e) if e.downcast_ref::<crate::transport::HttpNotFound>().is_some() => {
+                    // If it's a 404, then we know it's unknown
+                    return Err(unknown())
                 },
                 Err(e) => return Err(e),
                 Ok(buffer) => buffer
             };
-            let mut file = File::create(&location)?;
-            file.write_all(&buffer)?;
-            drop(file);
+            write_atomic(&location, |writer| {
+                writer.write_all(&buffer)?;
+                Ok(())
+            })?;
         }
         Ok(::serde_json::from_reader(File::open(&location)?)?)
     }
     /// Fetch spigot BuildData and ensure it contains the specified commit
-    fn fetch_build_data(&self, commit: &str) -> Result<BuildData, Error> {
+    fn fetch_build_data(&self, commit_id: Oid) -> Result<BuildData, Error> {
         let repo_location = self.cache_location.join("BuildData");
         fs::create_dir_all(repo_location.parent().unwrap())?;
-        let repo_url = "https://hub.spigotmc.org/stash/scm/spigot/builddata.git";
-        let commit_id = Oid::from_str(commit)?;
         let repo = if !repo_location.exists() {
-            println!("Fetching BuildData@{}", commit);
-            Repository::clone(repo_url, repo_location)?
+            self.clone_build_data(&repo_location, &format!("BuildData@{}", commit_id))?
         } else {
             let repo = Repository::open(repo_location)?;
             if repo.find_commit(commit_id).is_err() {
-                println!("Updating BuildData@{}", commit);
+                self.downloader.check_online(&format!("BuildData@{}", commit_id))?;
+                println!("Updating BuildData@{}", commit_id);
                 // Update the repo if we don't have the commit we want
-                let mut remote = repo.remote_anonymous(repo_url)?;
-                remote.fetch(
-                    &["master", format!(":{}", commit).as_ref()],
-                    None,
-                    None,
-                )?;
+                self.try_mirrors(|repo_url| {
+                    let mut remote = repo.remote_anonymous(repo_url)?;
+                    remote.fetch(
+                        &["master", format!(":{}", commit_id).as_ref()],
+                        None,
+                        None,
+                    )?;
+                    // Make sure this mirror actually had the commit we wanted
+                    repo.find_commit(commit_id)?;
+                    Ok(())
+                })?;
             }
             repo
         };
         Ok(BuildData(repo))
     }
+    /// Fetch the latest BuildData, so we know about all of its revisions
+    ///
+    /// If we're offline, we just use whatever we already have.
+    fn update_build_data(&self) -> Result<BuildData, Error> {
+        let repo_location = self.cache_location.join("BuildData");
+        fs::create_dir_all(repo_location.parent().unwrap())?;
+        if !repo_location.exists() {
+            return Ok(BuildData(self.clone_build_data(&repo_location, "BuildData")?))
+        }
+        let repo = Repository::open(repo_location)?;
+        if self.downloader.check_online("BuildData").is_ok() {
+            println!("Updating BuildData");
+            self.try_mirrors(|repo_url| {
+                let mut remote = repo.remote_anonymous(repo_url)?;
+                remote.fetch(&[MASTER_REFSPEC], None, None)?;
+                Ok(())
+            })?;
+        }
+        Ok(BuildData(repo))
+    }
+    fn clone_build_data(&self, repo_location: &Path, description: &str) -> Result<Repository, Error> {
+        self.downloader.check_online(description)?;
+        println!("Fetching {}", description);
+        self.try_mirrors(|repo_url| {
+            Repository::clone(repo_url, repo_location).map_err(|e| -> Error {
+                // Don't leave a partial clone behind for the next mirror
+                let _ = fs::remove_dir_all(repo_location);
+                e.into()
+            })
+        })
+    }
+    /// Try each BuildData mirror in order, until one of them succeeds
+    fn try_mirrors<T, F: FnMut(&str) -> Result<T, Error>>(&self, mut func: F) -> Result<T, Error> {
+        let mut first_error = None;
+        for repo_url in self.downloader.repository_urls(Upstream::BuildData)? {
+            match func(&repo_url) {
+                Ok(value) => return Ok(value),
+                Err(e) => if first_error.is_none() { first_error = Some(e) }
+            }
+        }
+        Err(first_error.expect("No BuildData mirrors"))
+    }
+}
+/// The ref we track the latest BuildData with, which `git clone` also creates
+const MASTER_REF: &str = "refs/remotes/origin/master";
+const MASTER_REFSPEC: &str = "+refs/heads/master:refs/remotes/origin/master";
+/// The md5 hash of the specified file, which BuildData uses to identify server jars
+fn md5_file(location: &Path) -> Result<String, Error> {
+    let mut input = BufReader::new(File::open(location)?);
+    let mut context = ::md5::Context::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let amount = input.read(&mut buffer)?;
+        if amount == 0 { break }
+        context.consume(&buffer[..amount]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+/// The minecraft version of the cached revision in the specified directory, if we can determine it
+pub(crate) fn cached_minecraft_version(revision_directory: &Path) -> Option<MinecraftVersion> {
+    let mut file = File::open(revision_directory.join(MAPPING_FILES[5])).ok()?;
+    BuildDataInfo::read(&mut file).ok()?.minecraft_version.parse().ok()
 }
-/// Contains all the mappings for a specific version
+struct RevisionRefs {
+    build_data: Oid,
+    craft_bukkit: Option<String>
+}
+
+/// Identifies a revision of spigot's mappings
+///
+/// This is parsed from either a minecraft version like `1.13.2` (for its latest revision),
+/// a spigot build number like `2000` or a (possibly abbreviated) BuildData commit.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SpigotRevision {
+    /// The latest revision for the minecraft version
+    Latest(MinecraftVersion),
+    /// The revision used by a spigot build
+    Build(u32),
+    /// The revision at a BuildData commit, which may be abbreviated
+    Commit(String)
+}
+impl FromStr for SpigotRevision {
+    type Err = InvalidSpigotRevision;
+
+    fn from_str(s: &str) -> Result<SpigotRevision, InvalidSpigotRevision> {
+        if let Ok(build) = s.parse::<u32>() {
+            Ok(SpigotRevision::Build(build))
+        } else if s.len() >= 7 && s.len() <= 40 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            Ok(SpigotRevision::Commit(s.to_ascii_lowercase()))
+        } else if let Ok(version) = s.parse::<MinecraftVersion>() {
+            Ok(SpigotRevision::Latest(version))
+        } else {
+            Err(InvalidSpigotRevision(s.into()))
+        }
+    }
+}
+impl Display for SpigotRevision {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            SpigotRevision::Latest(version) => write!(f, "{}", version),
+            SpigotRevision::Build(build) => write!(f, "{}", build),
+            SpigotRevision::Commit(ref hash) => f.write_str(hash),
+        }
+    }
+}
+impl Serialize for SpigotRevision {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where
+        S: Serializer {
+        serializer.serialize_str(&format!("{}", self))
+    }
+}
+impl<'de> Deserialize<'de> for SpigotRevision {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where
+        D: Deserializer<'de> {
+        struct RevisionVisitor;
+        impl<'de> ::serde::de::Visitor<'de> for RevisionVisitor {
+            type Value = SpigotRevision;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a SpigotRevision")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<SpigotRevision, E> where
+                E: de::Error, {
+                SpigotRevision::from_str(v).map_err(de::Error::custom)
+            }
+
+            #[inline]
+            fn visit_u64<E>(self, v: u64) -> Result<SpigotRevision, E> where
+                E: de::Error, {
+                if v > u64::from(u32::max_value()) {
+                    return Err(E::custom(format!("Invalid spigot build {}", v)))
+                }
+                Ok(SpigotRevision::Build(v as u32))
+            }
+        }
+        deserializer.deserialize_any(RevisionVisitor)
+    }
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid spigot revision {:?}", _0)]
+pub struct InvalidSpigotRevision(String);
+#[derive(Debug, Fail)]
+#[fail(display = "Unknown spigot revision {}", _0)]
+pub struct UnknownSpigotRevision(pub SpigotRevision);
+#[derive(Debug, Fail)]
+#[fail(display = "Server jar has hash {}, but spigot {} expects {} for minecraft {}", actual, revision, expected, minecraft_version)]
+pub struct MismatchedServerJar {
+    pub revision: SpigotRevision,
+    pub minecraft_version: MinecraftVersion,
+    pub expected: String,
+    pub actual: String
+}
+
+/// A revision of the mappings in BuildData
+#[derive(Clone, Debug)]
+pub struct SpigotRevisionInfo {
+    /// The full BuildData commit hash
+    pub commit: String,
+    pub minecraft_version: MinecraftVersion,
+    /// The time of the commit, in seconds since the unix epoch
+    pub time: i64,
+    /// The first line of the commit message
+    pub summary: String,
+    /// The md5 hash of the vanilla server jar the revision was made for
+    pub minecraft_hash: String
+}
+/// Contains all the mappings for a specific revision
 pub struct SpigotMappings {
+    pub minecraft_version: MinecraftVersion,
+    /// The md5 hash of the vanilla server jar these mappings apply to
+    pub minecraft_hash: String,
+    /// The BuildData commit these mappings were extracted from
+    pub build_data_commit: String,
     pub class_mappings: FrozenMappings,
     pub member_mappings: FrozenMappings,
-    pub chained_mappings: FrozenMappings
+    pub chained_mappings: FrozenMappings,
+    /// The access spigot widens, using the spigot names
+    pub access_transforms: Vec<AccessTransform>,
+    /// The package relocations used to compute the chained mappings
+    pub package_mappings: PackageMappings
+}
+impl SpigotMappings {
+    /// The chained mappings before the packages are relocated,
+    /// which leaves most classes in the default package.
+    #[inline]
+    pub fn raw_chained_mappings(&self) -> FrozenMappings {
+        self.class_mappings.clone().chain(self.member_mappings.clone())
+    }
+}
+
+/// The package relocations from BuildData's package mappings
+///
+/// The file has a line of the form `{original}/ {renamed}/` for each relocated package,
+/// where `./` is the default package.
+#[derive(Clone, Debug, Default)]
+pub struct PackageMappings(Vec<(String, String)>);
+impl PackageMappings {
+    pub fn parse(text: &str) -> Result<PackageMappings, InvalidPackageMapping> {
+        let mut relocations = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() { continue }
+            let mut parts = line.split_whitespace();
+            let error = || InvalidPackageMapping(line.into());
+            let original = parse_package(parts.next().ok_or_else(error)?);
+            let renamed = parse_package(parts.next().ok_or_else(error)?);
+            if parts.next().is_some() { return Err(error()) }
+            relocations.push((original, renamed));
+        }
+        Ok(PackageMappings(relocations))
+    }
+    /// The relocations, as pairs of original and renamed packages (in internal form)
+    #[inline]
+    pub fn relocations(&self) -> &[(String, String)] {
+        &self.0
+    }
+    /// Relocate the specified package, returning `None` if it's unchanged
+    ///
+    /// Subpackages are relocated along with their parent,
+    /// although the default package only matches itself.
+    pub fn relocate(&self, package: &str) -> Option<String> {
+        self.0.iter()
+            .filter(|&&(ref original, _)| {
+                package == original || (!original.is_empty()
+                    && package.starts_with(original.as_str())
+                    && package[original.len()..].starts_with('/'))
+            })
+            .max_by_key(|&&(ref original, _)| original.len())
+            .map(|&(ref original, ref renamed)| format!("{}{}", renamed, &package[original.len()..]))
+    }
+    /// Relocate the packages of all the renamed classes
+    #[inline]
+    pub fn apply(&self, mappings: FrozenMappings) -> FrozenMappings {
+        mappings.transform_packages(|package| self.relocate(package))
+    }
+}
+/// Determine the versioned server package from CraftBukkit's pom,
+/// which declares the version as a property like `<minecraft_version>1_13_R2</minecraft_version>`
+fn parse_craftbukkit_package(pom: &str) -> Option<String> {
+    const START: &str = "<minecraft_version>";
+    let start = pom.find(START)? + START.len();
+    let end = start + pom[start..].find("</minecraft_version>")?;
+    let version = pom[start..end].trim();
+    if version.is_empty() { return None }
+    Some(format!("{}/v{}", SERVER_PACKAGE, version))
 }
+#[derive(Debug, Fail)]
+#[fail(display = "CraftBukkit@{} doesn't declare its minecraft_version", _0)]
+pub struct MissingCraftBukkitPackage(String);
+fn parse_package(s: &str) -> String {
+    let s = s.trim_end_matches('/');
+    if s == "." { String::new() } else { s.into() }
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid package mapping {:?}", _0)]
+pub struct InvalidPackageMapping(String);
 
 #[derive(Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -180,6 +636,49 @@ impl BuildData {
             data: self,
         })
     }
+    /// Walk the history of master, finding the commits that changed the mappings
+    pub fn list_revisions(&self, version: Option<MinecraftVersion>) -> Result<Vec<SpigotRevisionInfo>, Error> {
+        let head = self.0.refname_to_id(MASTER_REF)
+            .or_else(|_| self.0.refname_to_id("HEAD"))?;
+        let mut revisions = Vec::new();
+        let mut next = Some(self.0.find_commit(head)?);
+        while let Some(commit) = next {
+            next = commit.parents().next();
+            // Only commits that changed the info or the mappings are new revisions
+            let contents = mapping_contents(&commit)?;
+            if contents.is_none() { continue }
+            if let Some(ref parent) = next {
+                if mapping_contents(parent)? == contents { continue }
+            }
+            // Ancient commits may not have a usable info.json
+            let data = match self.find_commit(commit.id()) {
+                Ok(data) => data,
+                Err(_) => continue
+            };
+            let minecraft_version = match data.minecraft_version() {
+                Ok(minecraft_version) => minecraft_version,
+                Err(_) => continue
+            };
+            if version.map_or(false, |version| version != minecraft_version) { continue }
+            revisions.push(SpigotRevisionInfo {
+                commit: format!("{}", commit.id()),
+                minecraft_version,
+                time: commit.time().seconds(),
+                summary: commit.summary().unwrap_or("").into(),
+                minecraft_hash: data.info.minecraft_hash.to_ascii_lowercase()
+            });
+        }
+        Ok(revisions)
+    }
+}
+/// The ids of the info and mappings directory at the specified commit,
+/// which change whenever the mappings do.
+fn mapping_contents(commit: &Commit) -> Result<Option<(Oid, Oid)>, Error> {
+    let tree = commit.tree()?;
+    Ok(match (tree.get_path(Path::new("info.json")), tree.get_path(Path::new("mappings"))) {
+        (Ok(info), Ok(mappings)) => Some((info.id(), mappings.id())),
+        _ => None
+    })
 }
 fn sanitize_class_data(s: &mut String) {
     let mut corrected = String::with_capacity(s.len());
@@ -223,6 +722,50 @@ impl<'a> BuildDataCommit<'a> {
         self.load_member_mapping_data(&mut buffer)?;
         Ok(CompactSrgMappingsFormat::parse_text(&buffer)?)
     }
+    /// The minecraft version this commit's mappings are for
+    pub fn minecraft_version(&self) -> Result<MinecraftVersion, Error> {
+        Ok(self.info.minecraft_version.parse()?)
+    }
+    /// Read the raw text of the info, which is needed to interpret the mapping files
+    pub fn read_info(&self) -> Result<String, Error> {
+        let mut buffer = String::new();
+        self.load(Path::new("info.json"), &mut buffer)?;
+        Ok(buffer)
+    }
+    /// Read the raw text of the package mappings
+    pub fn read_package_mappings(&self) -> Result<String, Error> {
+        let mut path = PathBuf::from("mappings");
+        path.push(&self.info.package_mappings);
+        let mut buffer = String::new();
+        self.load(&path, &mut buffer)?;
+        Ok(buffer)
+    }
+    /// Read the raw text of the access transforms
+    pub fn read_access_transforms(&self) -> Result<String, Error> {
+        let mut path = PathBuf::from("mappings");
+        path.push(&self.info.access_transforms);
+        let mut buffer = String::with_capacity(8 * 1024);
+        self.load(&path, &mut buffer)?;
+        Ok(buffer)
+    }
+    /// Record the raw mapping files we're reading from in the specified manifest
+    pub fn record_sources(&self, manifest: &mut CacheManifest) -> Result<(), Error> {
+        let names = [
+            &self.info.class_mappings,
+            &self.info.member_mappings,
+            &self.info.access_transforms,
+            &self.info.package_mappings
+        ];
+        for name in &names {
+            let mut path = PathBuf::from("mappings");
+            path.push(name);
+            let mut buffer = String::new();
+            self.load(&path, &mut buffer)?;
+            let location = format!("BuildData@{}:{}", self.commit.id(), path.display());
+            manifest.record_source(&location, buffer.as_bytes());
+        }
+        Ok(())
+    }
     fn load_class_mapping_data(&self, buffer: &mut String) -> Result<(), Error> {
         let mut path = PathBuf::from("mappings");
         path.push(&self.info.class_mappings);
@@ -235,4 +778,42 @@ impl<'a> BuildDataCommit<'a> {
         self.load(&path, buffer)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn relocate_packages() {
+        let packages = PackageMappings::parse(
+            "./ net/minecraft/server/\n\
+            org/bukkit/craftbukkit/libs/ org/bukkit/craftbukkit/libs/shaded/\n"
+        ).unwrap();
+        assert_eq!(packages.relocate(""), Some("net/minecraft/server".into()));
+        assert_eq!(packages.relocate("com/mojang/authlib"), None);
+        assert_eq!(
+            packages.relocate("org/bukkit/craftbukkit/libs/jline"),
+            Some("org/bukkit/craftbukkit/libs/shaded/jline".into())
+        );
+        assert_eq!(packages.relocate("org/bukkit/craftbukkit/libsfoo"), None);
+    }
+    #[test]
+    fn parse_revisions() {
+        assert_eq!("1.13.2".parse::<SpigotRevision>().unwrap(), SpigotRevision::Latest(MinecraftVersion::release(1, 13, 2)));
+        assert_eq!("2000".parse::<SpigotRevision>().unwrap(), SpigotRevision::Build(2000));
+        assert_eq!(
+            "A6E4D2C5b".parse::<SpigotRevision>().unwrap(),
+            SpigotRevision::Commit("a6e4d2c5b".into())
+        );
+        assert!("abc".parse::<SpigotRevision>().is_err());
+    }
+    #[test]
+    fn craftbukkit_package() {
+        assert_eq!(
+            parse_craftbukkit_package(
+                "<properties>\n    <minecraft_version>1_13_R2</minecraft_version>\n</properties>"
+            ),
+            Some("net/minecraft/server/v1_13_R2".into())
+        );
+        assert_eq!(parse_craftbukkit_package("<properties></properties>"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! The transports we use to access upstream data.
+//!
+//! All of our network access goes through a `Transport`,
+//! so the loaders can be exercised against canned data instead of the real servers.
+use std::io::{self, Write, Cursor};
+use std::path::PathBuf;
+use std::fs;
+
+use failure::Error;
+use failure_derive::Fail;
+use indexmap::IndexMap;
+use curl::easy::Easy;
+
+pub trait Transport: Send + Sync {
+    /// Fetch the contents of the specified url
+    ///
+    /// If the url doesn't exist, this should fail with `HttpNotFound`.
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error>;
+    /// Resolve the location we should clone the specified git repository from
+    #[inline]
+    fn repository_url(&self, url: &str) -> Result<String, Error> {
+        Ok(url.into())
+    }
+}
+
+/// The default transport, which actually accesses the network using curl
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CurlTransport;
+impl Transport for CurlTransport {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::with_capacity(2048);
+        {
+            let mut cursor = Cursor::new(buffer);
+            download(url, &mut cursor)?;
+            buffer = cursor.into_inner();
+        }
+        Ok(buffer)
+    }
+}
+
+fn download<W: Write>(url: &str, output: &mut W) -> Result<(), Error> {
+    let mut easy = Easy::new();
+    easy.url(url)?;
+    easy.fail_on_error(true)?;
+    let mut error: Option<io::Error> = None;
+    let result = {
+        let mut transfer = easy.transfer();
+        transfer.write_function(
+            |data| if let Err(e) = output.write_all(data) {
+                error = Some(e);
+                Ok(0)
+            } else {
+                Ok(data.len())
+            },
+        )?;
+        transfer.perform()
+    };
+    if easy.response_code()? == 404 {
+        return Err(HttpNotFound.into())
+    }
+    match result {
+        Err(e) => {
+            if let Some(actual_error) = error.take() {
+                Err(actual_error.into())
+            } else {
+                Err(e.into())
+            }
+        }
+        Ok(_) => {
+            assert!(error.is_none());
+            Ok(())
+        }
+    }
+}
+
+/// Serves canned data from memory, treating every unknown url as a 404
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    entries: IndexMap<String, Vec<u8>>,
+    repositories: IndexMap<String, PathBuf>
+}
+impl MemoryTransport {
+    #[inline]
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+    #[inline]
+    pub fn insert<U: Into<String>, D: Into<Vec<u8>>>(&mut self, url: U, data: D) {
+        self.entries.insert(url.into(), data.into());
+    }
+    /// Redirect the specified git repository to a local clone
+    #[inline]
+    pub fn insert_repository<U: Into<String>>(&mut self, url: U, location: PathBuf) {
+        self.repositories.insert(url.into(), location);
+    }
+}
+impl Transport for MemoryTransport {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        match self.entries.get(url) {
+            Some(data) => Ok(data.clone()),
+            None => Err(HttpNotFound.into())
+        }
+    }
+    fn repository_url(&self, url: &str) -> Result<String, Error> {
+        match self.repositories.get(url) {
+            Some(location) => Ok(location.to_string_lossy().into_owned()),
+            None => Err(HttpNotFound.into())
+        }
+    }
+}
+
+/// Serves data from a local directory which mirrors the layout of the upstream servers
+///
+/// For example, `http://export.mcpbot.bspk.rs/versions.json`
+/// is served from `{root}/export.mcpbot.bspk.rs/versions.json`.
+/// Since each query is a different file, it's kept in the file name with the `?` escaped,
+/// so `https://example.com/pom.xml?at=abc` is served from `{root}/example.com/pom.xml%3Fat=abc`.
+/// Git repositories are expected to be cloned to the corresponding location.
+#[derive(Clone, Debug)]
+pub struct LocalDirectoryTransport {
+    root: PathBuf
+}
+impl LocalDirectoryTransport {
+    #[inline]
+    pub fn new(root: PathBuf) -> LocalDirectoryTransport {
+        LocalDirectoryTransport { root }
+    }
+    /// The local path corresponding to the specified url
+    pub fn resolve(&self, url: &str) -> PathBuf {
+        let without_scheme = match url.find("://") {
+            Some(index) => &url[(index + 3)..],
+            None => url
+        };
+        let (path, query) = match without_scheme.find('?') {
+            Some(index) => (&without_scheme[..index], Some(&without_scheme[(index + 1)..])),
+            None => (without_scheme, None)
+        };
+        let mut parts = path.split('/')
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if let Some(query) = query {
+            let escaped = format!("%3F{}", query.replace('/', "%2F"));
+            match parts.last_mut() {
+                Some(last) => last.push_str(&escaped),
+                None => parts.push(escaped)
+            }
+        }
+        let mut result = self.root.clone();
+        for part in &parts {
+            result.push(part);
+        }
+        result
+    }
+}
+impl Transport for LocalDirectoryTransport {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let location = self.resolve(url);
+        if !location.is_file() {
+            return Err(HttpNotFound.into())
+        }
+        Ok(fs::read(&location)?)
+    }
+    fn repository_url(&self, url: &str) -> Result<String, Error> {
+        let location = self.resolve(url);
+        if !location.exists() {
+            return Err(HttpNotFound.into())
+        }
+        Ok(location.to_string_lossy().into_owned())
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "HTTP 404 not found")]
+pub struct HttpNotFound;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn resolve_local() {
+        let transport = LocalDirectoryTransport::new(PathBuf::from("fixtures"));
+        assert_eq!(
+            transport.resolve("http://export.mcpbot.bspk.rs/versions.json"),
+            PathBuf::from("fixtures/export.mcpbot.bspk.rs/versions.json")
+        );
+        assert_eq!(
+            transport.resolve("https://hub.spigotmc.org/stash/scm/spigot/builddata.git"),
+            PathBuf::from("fixtures/hub.spigotmc.org/stash/scm/spigot/builddata.git")
+        );
+        assert_eq!(
+            transport.resolve("https://example.com/raw/pom.xml?at=abc"),
+            PathBuf::from("fixtures/example.com/raw/pom.xml%3Fat=abc")
+        );
+        assert_eq!(
+            transport.resolve("https://example.com/raw/pom.xml?at=refs/heads/master"),
+            PathBuf::from("fixtures/example.com/raw/pom.xml%3Fat=refs%2Fheads%2Fmaster")
+        );
+    }
+    #[test]
+    fn memory_not_found() {
+        let mut transport = MemoryTransport::new();
+        transport.insert("http://example.com/found", &b"data"[..]);
+        assert_eq!(transport.fetch("http://example.com/found").unwrap(), b"data");
+        let error = transport.fetch("http://example.com/missing").unwrap_err();
+        assert!(error.downcast_ref::<HttpNotFound>().is_some());
+    }
+    #[test]
+    fn local_loaders() {
+        use std::sync::Arc;
+        use srglib::prelude::*;
+        use zip::ZipWriter;
+        use zip::write::FileOptions;
+        use crate::MinecraftVersion;
+        use crate::spigot::SpigotRevision;
+        use crate::cache::{MinecraftMappingsCache, CacheSettings};
+        let location = ::std::env::temp_dir()
+            .join(format!("local-loaders-{}", ::std::process::id()));
+        let transport = LocalDirectoryTransport::new(location.join("upstream"));
+        let serve = |url: &str, data: &[u8]| {
+            let file = transport.resolve(url);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, data).unwrap();
+        };
+        let srg = "CL: a net/minecraft/server/MinecraftServer\n\
+            FD: a/b net/minecraft/server/MinecraftServer/field_1234_b\n";
+        let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+        archive.start_file("joined.srg", FileOptions::default()).unwrap();
+        archive.write_all(srg.as_bytes()).unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+        serve(
+            "http://export.mcpbot.bspk.rs/versions.json",
+            br#"{"1.12.2": {"snapshot": [20180814], "stable": [39]}}"#
+        );
+        serve("http://files.minecraftforge.net/maven/de/oceanlabs/mcp/mcp/1.12.2/mcp-1.12.2-srg.zip", &archive);
+        let revisions = [("1.12.2", '1', "aaaaaaa", "1_12_R1"), ("1.13.2", '2', "bbbbbbb", "1_13_R2")];
+        for &(version, build_data, craft_bukkit, package) in &revisions {
+            let info = format!(
+                r#"{{"name": "{}", "refs": {{"BuildData": "{}", "Bukkit": "x", "CraftBukkit": "{}", "Spigot": "x"}}}}"#,
+                version, build_data.to_string().repeat(40), craft_bukkit
+            );
+            serve(&format!("https://hub.spigotmc.org/versions/{}.json", version), info.as_bytes());
+            // The poms only differ by their query, so they'd collide if we dropped it
+            serve(
+                &format!("https://hub.spigotmc.org/stash/projects/SPIGOT/repos/craftbukkit/raw/pom.xml?at={}", craft_bukkit),
+                format!("<minecraft_version>{}</minecraft_version>", package).as_bytes()
+            );
+        }
+        let settings = CacheSettings { transport: Arc::new(transport.clone()), ..CacheSettings::default() };
+        let cache = MinecraftMappingsCache::setup_with(location.join("cache"), settings).unwrap();
+        let version = "1.12.2".parse::<MinecraftVersion>().unwrap();
+        assert_eq!(cache.known_minecraft_versions(), vec![version]);
+        let expected = SrgMappingsFormat::write_string(&SrgMappingsFormat::parse_text(srg).unwrap());
+        assert_eq!(SrgMappingsFormat::write_string(&cache.load_srg_mappings(version).unwrap()), expected);
+        assert_eq!(
+            cache.load_craftbukkit_package(&SpigotRevision::Latest(version)).unwrap(),
+            "net/minecraft/server/v1_12_R1"
+        );
+        assert_eq!(
+            cache.load_craftbukkit_package(&SpigotRevision::Latest("1.13.2".parse().unwrap())).unwrap(),
+            "net/minecraft/server/v1_13_R2"
+        );
+        // Everything we loaded should now be served from the cache
+        let offline = CacheSettings { offline: true, ..CacheSettings::default() };
+        let cache = MinecraftMappingsCache::setup_with(location.join("cache"), offline).unwrap();
+        assert_eq!(SrgMappingsFormat::write_string(&cache.load_srg_mappings(version).unwrap()), expected);
+        fs::remove_dir_all(&location).unwrap();
+    }
+}
@@ -1,58 +1,438 @@
-use std::io::{self, Write, Cursor};
 use std::hash::{Hash, BuildHasher};
-use std::ops::Deref;
 use std::collections::hash_map::RandomState;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::io::{BufWriter, Write};
+use std::fs::{self, File};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::str;
 
-use failure::Error;
-use failure_derive::Fail;
-use indexmap::{IndexMap, map::Entry};
+use failure::{Error, Fail, Backtrace, format_err};
+use parking_lot::{Mutex, Condvar};
+use crossbeam::atomic::ArcCell;
+use indexmap::IndexMap;
+use serde_derive::Serialize;
 use git2::{Repository, Commit};
-use curl::easy::Easy;
 
+use crate::cache::NotCachedOffline;
+use crate::transport::{Transport, HttpNotFound};
+use crate::mirrors::{MirrorConfig, Upstream};
+
+/// A map that evicts its least recently used entries once it exceeds its budget
+///
+/// The budget is in approximate bytes, using the sizes estimated by whoever inserts the entries.
+/// Lookups only need a shared reference, so the cache can be read through an `ArcCell`.
+/// Clones share their recency and statistics with the original,
+/// so the copy-on-write updates of the `ArcCell` don't lose track of any reads.
 #[derive(Clone, Debug)]
 pub struct LruCache<K: Eq + Hash, V, S: BuildHasher = RandomState> {
-    capacity: usize,
-    map: IndexMap<K, V, S>
+    budget: usize,
+    size: usize,
+    map: IndexMap<K, LruEntry<V>, S>,
+    clock: Arc<AtomicUsize>,
+    counters: Arc<LruCounters>
+}
+#[derive(Clone, Debug)]
+struct LruEntry<V> {
+    value: V,
+    size: usize,
+    last_used: Arc<AtomicUsize>
+}
+#[derive(Debug, Default)]
+struct LruCounters {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    evictions: AtomicUsize
 }
 impl<K: Eq + Hash, V> LruCache<K, V> {
     #[inline]
-    pub fn new(capacity: usize) -> LruCache<K, V> {
-        LruCache { capacity, map: IndexMap::with_capacity(capacity) }
-    }
-    fn cleanup(&mut self) {
-        assert!(self.map.len() >= self.capacity);
-        let needed_removed = self.map.len() - self.capacity;
-        let mut index = 0;
-        self.map.retain(|_, _| {
-            let should_remove = index < needed_removed;
-            index += 1;
-            should_remove
+    pub fn new(budget: usize) -> LruCache<K, V> {
+        LruCache {
+            budget, size: 0,
+            map: IndexMap::new(),
+            clock: Arc::new(AtomicUsize::new(0)),
+            counters: Arc::default()
+        }
+    }
+}
+impl<K: Eq + Hash, V, S: BuildHasher> LruCache<K, V, S> {
+    /// Lookup the specified key, marking it as the most recently used entry
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.map.get(key) {
+            Some(entry) => {
+                entry.last_used.store(self.tick(), Ordering::Relaxed);
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Some(&entry.value)
+            },
+            None => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+    /// Lookup the specified key without affecting its recency or the statistics
+    #[inline]
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|entry| &entry.value)
+    }
+    /// Insert an entry with the specified approximate size in bytes,
+    /// evicting the least recently used entries until we fit in the budget again.
+    ///
+    /// The new entry is never evicted, even if it's larger than the entire budget.
+    pub fn insert(&mut self, key: K, value: V, size: usize) -> Option<V> {
+        let entry = LruEntry { value, size, last_used: Arc::new(AtomicUsize::new(self.tick())) };
+        let (mut inserted, old) = self.map.insert_full(key, entry);
+        self.size += size;
+        let old = old.map(|old| {
+            self.size -= old.size;
+            old.value
         });
-        assert!(self.map.len() <= self.capacity);
-    }
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        let (old, cleanup) = match self.map.entry(key) {
-            Entry::Occupied(mut entry) => (Some(entry.insert(value)), false),
-            Entry::Vacant(entry) => {
-                entry.insert(value);
-                (None, true)
+        while self.size > self.budget && self.map.len() > 1 {
+            let (victim, _) = self.map.values().enumerate()
+                .filter(|&(index, _)| index != inserted)
+                .min_by_key(|&(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .unwrap();
+            let last = self.map.len() - 1;
+            let (_, evicted) = self.map.swap_remove_index(victim).unwrap();
+            // Removing swaps the last entry into the victim's place
+            if inserted == last {
+                inserted = victim;
             }
-        };
-        if cleanup && self.map.len() >= self.capacity {
-            self.cleanup()
+            self.size -= evicted.size;
+            self.counters.evictions.fetch_add(1, Ordering::Relaxed);
         }
         old
     }
+    /// Remove every entry whose key doesn't match the predicate
+    pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut keep: F) {
+        let size = &mut self.size;
+        self.map.retain(|key, entry| {
+            let kept = keep(key);
+            if !kept {
+                *size -= entry.size;
+            }
+            kept
+        });
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    pub fn stats(&self) -> LruStats {
+        LruStats {
+            entries: self.map.len(),
+            size: self.size,
+            budget: self.budget,
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed)
+        }
+    }
+    #[inline]
+    fn tick(&self) -> usize {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+/// A snapshot of the usage of a `LruCache`
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct LruStats {
+    pub entries: usize,
+    /// The approximate number of bytes used by the entries
+    pub size: usize,
+    pub budget: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize
+}
+
+/// Deduplicates concurrent loads, so each key only has a single loader at a time
+///
+/// Anyone asking for a key that's already being loaded waits for that loader and shares its result,
+/// while loads of different keys proceed in parallel.
+/// If the load fails, the loader and everyone waiting on it get the same `SharedError`,
+/// so use `find_cause` rather than `downcast_ref` to check what went wrong.
+pub struct InFlightLoads<K: Eq + Hash, V> {
+    pending: Mutex<IndexMap<K, Arc<PendingLoad<V>>>>
+}
+struct PendingLoad<V> {
+    result: Mutex<Option<Result<V, SharedError>>>,
+    finished: Condvar
+}
+impl<K: Eq + Hash + Clone, V: Clone> InFlightLoads<K, V> {
+    #[inline]
+    pub fn new() -> InFlightLoads<K, V> {
+        InFlightLoads { pending: Mutex::new(IndexMap::new()) }
+    }
+    /// Load the specified key, or wait for the existing loader if someone else is already loading it
+    ///
+    /// The loader should publish its result before returning,
+    /// since anyone who arrives afterwards will start a new load.
+    pub fn load<F>(&self, key: &K, loader: F) -> Result<V, Error>
+        where F: FnOnce() -> Result<V, Error> {
+        let (pending, existing) = {
+            let mut pending = self.pending.lock();
+            match pending.get(key) {
+                Some(existing) => (existing.clone(), true),
+                None => {
+                    let created = Arc::new(PendingLoad {
+                        result: Mutex::new(None),
+                        finished: Condvar::new()
+                    });
+                    pending.insert(key.clone(), created.clone());
+                    (created, false)
+                }
+            }
+        };
+        if existing {
+            let mut result = pending.result.lock();
+            while result.is_none() {
+                pending.finished.wait(&mut result);
+            }
+            return Ok(result.clone().unwrap()?)
+        }
+        // This wakes up the waiters even if the loader panics
+        let _finish = FinishLoad { loads: self, key, pending: &pending };
+        let result = loader().map_err(|cause| SharedError(Arc::new(cause)));
+        *pending.result.lock() = Some(result.clone());
+        Ok(result?)
+    }
+}
+impl<K: Eq + Hash + Clone, V: Clone> Default for InFlightLoads<K, V> {
+    #[inline]
+    fn default() -> Self {
+        InFlightLoads::new()
+    }
+}
+struct FinishLoad<'a, K: Eq + Hash + 'a, V: 'a> {
+    loads: &'a InFlightLoads<K, V>,
+    key: &'a K,
+    pending: &'a PendingLoad<V>
+}
+impl<'a, K: Eq + Hash, V> Drop for FinishLoad<'a, K, V> {
+    fn drop(&mut self) {
+        self.loads.pending.lock().remove(self.key);
+        let mut result = self.pending.result.lock();
+        if result.is_none() {
+            *result = Some(Err(SharedError(Arc::new(format_err!("Loader panicked")))));
+        }
+        self.pending.finished.notify_all();
+    }
+}
+/// An error from a load that was shared by everyone waiting on it
+///
+/// The original error is its cause, so it can still be found with `find_cause`.
+#[derive(Clone, Debug)]
+pub struct SharedError(Arc<Error>);
+impl SharedError {
+    #[inline]
+    pub fn error(&self) -> &Error {
+        &self.0
+    }
+}
+impl Display for SharedError {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&*self.0, f)
+    }
+}
+impl Fail for SharedError {
+    #[inline]
+    fn cause(&self) -> Option<&dyn Fail> {
+        Some(self.0.as_fail())
+    }
+    #[inline]
+    fn backtrace(&self) -> Option<&Backtrace> {
+        Some(self.0.backtrace())
+    }
+}
+
+/// Find the first error of the specified type in the chain of causes,
+/// which also looks through any `SharedError`s.
+pub fn find_cause<T: Fail>(error: &Error) -> Option<&T> {
+    error.iter_chain().filter_map(|fail| fail.downcast_ref::<T>()).next()
+}
+
+/// A map that can hold the values of a `LoadingCache`
+pub trait CacheMap: Clone {
+    type Key: Eq + Hash + Clone;
+    type Value: Clone;
+    /// Lookup the value for a reader
+    fn lookup(&self, key: &Self::Key) -> Option<&Self::Value>;
+    /// Lookup the value without counting it as a use
+    fn peek(&self, key: &Self::Key) -> Option<&Self::Value>;
+    /// Store the value, along with its approximate size in bytes
+    fn store(&mut self, key: Self::Key, value: Self::Value, size: usize);
+}
+impl<K: Eq + Hash + Clone, V: Clone> CacheMap for IndexMap<K, V> {
+    type Key = K;
+    type Value = V;
+    #[inline]
+    fn lookup(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+    #[inline]
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+    #[inline]
+    fn store(&mut self, key: K, value: V, _size: usize) {
+        // We never evict anything, so we don't need the size
+        self.insert(key, value);
+    }
+}
+impl<K: Eq + Hash + Clone, V: Clone> CacheMap for LruCache<K, V> {
+    type Key = K;
+    type Value = V;
+    #[inline]
+    fn lookup(&self, key: &K) -> Option<&V> {
+        LruCache::get(self, key)
+    }
+    #[inline]
+    fn peek(&self, key: &K) -> Option<&V> {
+        LruCache::peek(self, key)
+    }
+    #[inline]
+    fn store(&mut self, key: K, value: V, size: usize) {
+        LruCache::insert(self, key, value, size);
+    }
+}
+
+/// Values that are loaded on demand, and can then be read without any locking
+///
+/// The values are read through an `ArcCell`, which is updated by copying the map whenever we load something.
+/// Each key only has a single loader at a time, but different keys are loaded in parallel.
+pub struct LoadingCache<M: CacheMap> {
+    values: ArcCell<M>,
+    loads: InFlightLoads<M::Key, M::Value>,
+    /// Serializes updates to the values, but is never held while loading them
+    lock: Mutex<()>
+}
+impl<M: CacheMap> LoadingCache<M> {
+    #[inline]
+    pub fn new(values: M) -> LoadingCache<M> {
+        LoadingCache {
+            values: ArcCell::new(Arc::new(values)),
+            loads: InFlightLoads::new(),
+            lock: Mutex::new(())
+        }
+    }
+    /// The values that are currently loaded
+    #[inline]
+    pub fn values(&self) -> Arc<M> {
+        self.values.get()
+    }
+    #[inline]
+    pub fn get(&self, key: &M::Key) -> Option<M::Value> {
+        self.values.get().lookup(key).cloned()
+    }
+    /// Get the value of the specified key, loading it if it isn't already loaded
+    ///
+    /// The loader gives the value along with its approximate size in bytes.
+    #[inline]
+    pub fn load_sized<F>(&self, key: &M::Key, loader: F) -> Result<M::Value, Error>
+        where F: FnOnce() -> Result<(M::Value, usize), Error> {
+        if let Some(value) = self.get(key) {
+            return Ok(value)
+        }
+        self.load_fallback(key, loader)
+    }
+    #[cold]
+    fn load_fallback<F>(&self, key: &M::Key, loader: F) -> Result<M::Value, Error>
+        where F: FnOnce() -> Result<(M::Value, usize), Error> {
+        self.loads.load(key, || {
+            // Someone else could've already finished loading it before we started
+            if let Some(value) = self.values.get().peek(key) {
+                return Ok(value.clone())
+            }
+            let (value, size) = loader()?;
+            self.insert(key.clone(), value.clone(), size);
+            Ok(value)
+        })
+    }
+    /// Load the value of the specified key again, replacing anything that's already loaded
+    ///
+    /// If someone else is already loading the key we wait for them and try again,
+    /// since they'd just give us the old value.
+    pub fn reload_sized<F>(&self, key: &M::Key, loader: F) -> Result<M::Value, Error>
+        where F: FnOnce() -> Result<(M::Value, usize), Error> {
+        let mut loader = Some(loader);
+        loop {
+            let result = self.loads.load(key, || {
+                let (value, size) = (loader.take().unwrap())()?;
+                self.insert(key.clone(), value.clone(), size);
+                Ok(value)
+            });
+            if loader.is_none() {
+                return result
+            }
+        }
+    }
+    /// Insert a value directly, replacing any existing value
+    pub fn insert(&self, key: M::Key, value: M::Value, size: usize) {
+        self.update(|values| values.store(key, value, size))
+    }
+    /// Modify the loaded values, like to forget the ones that are outdated
+    pub fn update<F: FnOnce(&mut M)>(&self, func: F) {
+        let _guard = self.lock.lock();
+        let mut updated = M::clone(&self.values.get());
+        func(&mut updated);
+        self.values.set(Arc::new(updated));
+    }
+}
+impl<K: Eq + Hash + Clone, V: Clone> LoadingCache<IndexMap<K, V>> {
+    /// Get the value of the specified key, loading it if it isn't already loaded
+    #[inline]
+    pub fn load<F>(&self, key: &K, loader: F) -> Result<V, Error>
+        where F: FnOnce() -> Result<V, Error> {
+        self.load_sized(key, || Ok((loader()?, 0)))
+    }
+    /// Load the value of the specified key again, replacing anything that's already loaded
+    #[inline]
+    pub fn reload<F>(&self, key: &K, loader: F) -> Result<V, Error>
+        where F: FnOnce() -> Result<V, Error> {
+        self.reload_sized(key, || Ok((loader()?, 0)))
+    }
+}
+impl<K: Eq + Hash + Clone, V: Clone> Default for LoadingCache<IndexMap<K, V>> {
+    #[inline]
+    fn default() -> Self {
+        LoadingCache::new(IndexMap::new())
+    }
 }
-impl<K: Eq + Hash, V, S: BuildHasher> Deref for LruCache<K, V, S> {
-    type Target = IndexMap<K, V, S>;
 
-    #[inline(always)]
-    fn deref(&self) -> &IndexMap<K, V, S> {
-        &self.map
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Atomically write to the specified file,
+/// by writing to a temporary file and then renaming it into place.
+///
+/// This ensures a killed process never leaves a truncated file behind.
+pub fn write_atomic<F>(location: &Path, func: F) -> Result<(), Error>
+    where F: FnOnce(&mut BufWriter<File>) -> Result<(), Error> {
+    let mut temp_name = location.file_name()
+        .expect("Invalid file name").to_os_string();
+    // Include a counter so concurrent writes from the same process don't collide
+    let unique = TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst);
+    temp_name.push(format!(".tmp-{}-{}", process::id(), unique));
+    let temp_location = PathBuf::from(location).with_file_name(temp_name);
+    let result: Result<(), Error> = (|| {
+        let mut writer = BufWriter::new(File::create(&temp_location)?);
+        func(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(&temp_location, location)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_location);
     }
+    result
 }
 
 pub fn load_from_commit(repo: &Repository, commit: &Commit, relative_path: &Path, buffer: &mut String) -> Result<(), Error> {
@@ -70,51 +450,164 @@ pub fn load_from_commit(repo: &Repository, commit: &Commit, relative_path: &Path
     Ok(())
 }
 
-#[inline]
-pub fn download_buffer(url: &str) -> Result<Vec<u8>, Error> {
-    let mut buffer = Vec::with_capacity(2048);
-    {
-        let mut cursor = Cursor::new(buffer);
-        download(url, &mut cursor)?;
-        buffer = cursor.into_inner();
-    }
-    Ok(buffer)
+/// Performs all our network access, refusing to touch the network in offline mode
+#[derive(Clone)]
+pub(crate) struct Downloader {
+    offline: bool,
+    transport: Arc<dyn Transport>,
+    mirrors: Arc<MirrorConfig>
 }
-
-fn download<W: Write>(url: &str, output: &mut W) -> Result<(), Error> {
-    let mut easy = Easy::new();
-    easy.url(url)?;
-    easy.fail_on_error(true)?;
-    let mut error: Option<io::Error> = None;
-    let result = {
-        let mut transfer = easy.transfer();
-        transfer.write_function(
-            |data| if let Err(e) = output.write_all(data) {
-                error = Some(e);
-                Ok(0)
-            } else {
-                Ok(data.len())
-            },
-        )?;
-        transfer.perform()
-    };
-    if easy.response_code()? == 404 {
-        return Err(HttpNotFound.into())
-    }
-    match result {
-        Err(e) => {
-            if let Some(actual_error) = error.take() {
-                Err(actual_error.into())
-            } else {
-                Err(e.into())
+impl Downloader {
+    #[inline]
+    pub fn new(offline: bool, transport: Arc<dyn Transport>, mirrors: MirrorConfig) -> Downloader {
+        Downloader { offline, transport, mirrors: Arc::new(mirrors) }
+    }
+    #[inline]
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+    /// Fail with a `NotCachedOffline` error if we're not allowed to access the network
+    #[inline]
+    pub fn check_online(&self, what: &str) -> Result<(), NotCachedOffline> {
+        if self.offline {
+            Err(NotCachedOffline(what.into()))
+        } else {
+            Ok(())
+        }
+    }
+    /// Download the specified path relative to the upstream source
+    ///
+    /// Each mirror is tried in order until one of them succeeds.
+    /// If all of them fail, we report the first error that wasn't a 404,
+    /// so we only claim something is missing when every mirror agrees.
+    pub fn download_buffer(&self, upstream: Upstream, path: &str) -> Result<Vec<u8>, Error> {
+        self.check_online(&format!("{} {}", upstream, path))?;
+        let mut first_error: Option<Error> = None;
+        for base in self.mirrors.mirrors(upstream) {
+            let url = format!("{}/{}", base.trim_end_matches('/'), path);
+            match self.transport.fetch(&url) {
+                Ok(buffer) => return Ok(buffer),
+                Err(error) => {
+                    let replace = match first_error {
+                        None => true,
+                        Some(ref first) => {
+                            first.downcast_ref::<HttpNotFound>().is_some()
+                                && error.downcast_ref::<HttpNotFound>().is_none()
+                        }
+                    };
+                    if replace {
+                        first_error = Some(error);
+                    }
+                }
             }
         }
-        Ok(_) => {
-            assert!(error.is_none());
-            Ok(())
+        Err(first_error.unwrap_or_else(|| HttpNotFound.into()))
+    }
+    /// Download an absolute url that was handed to us by the upstream source
+    ///
+    /// If it's on the official server, we try each of the mirrors instead.
+    pub fn download_url(&self, upstream: Upstream, url: &str) -> Result<Vec<u8>, Error> {
+        let official = upstream.official_url();
+        if url.starts_with(official) {
+            self.download_buffer(upstream, url[official.len()..].trim_start_matches('/'))
+        } else {
+            self.check_online(url)?;
+            self.transport.fetch(url)
+        }
+    }
+    /// Resolve the locations we should try to clone the specified git repository from
+    pub fn repository_urls(&self, upstream: Upstream) -> Result<Vec<String>, Error> {
+        self.check_online(&format!("{}", upstream))?;
+        let mut first_error: Option<Error> = None;
+        let mut result = Vec::new();
+        for url in self.mirrors.mirrors(upstream) {
+            match self.transport.repository_url(url) {
+                Ok(resolved) => result.push(resolved),
+                Err(error) => if first_error.is_none() { first_error = Some(error) }
+            }
+        }
+        match first_error {
+            Some(error) if result.is_empty() => Err(error),
+            _ => Ok(result)
         }
     }
 }
-#[derive(Debug, Fail)]
-#[fail(display = "HTTP 404 not found")]
-pub struct HttpNotFound;
\ No newline at end of file
+impl Debug for Downloader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Downloader")
+            .field("offline", &self.offline)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn lru_eviction() {
+        let mut cache = LruCache::new(30);
+        cache.insert("a", 1, 10);
+        cache.insert("b", 2, 10);
+        cache.insert("c", 3, 10);
+        // Reading an entry should protect it from eviction
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.insert("d", 4, 10);
+        assert_eq!(cache.peek(&"b"), None);
+        assert_eq!(cache.peek(&"a"), Some(&1));
+        // Oversized entries evict everything else, but are still kept
+        cache.insert("e", 5, 100);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.peek(&"e"), Some(&5));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 0, 4));
+        assert_eq!(stats.size, 100);
+    }
+    #[test]
+    fn shared_loads() {
+        use std::thread;
+        let loads = Arc::new(InFlightLoads::<u32, u32>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4).map(|_| {
+            let (loads, calls) = (loads.clone(), calls.clone());
+            thread::spawn(move || {
+                loads.load(&1, || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    /*
+                     * Block until the other three are waiting on us,
+                     * which each hold a reference to the pending load along with us and the map.
+                     */
+                    let pending = loads.pending.lock()[&1].clone();
+                    while Arc::strong_count(&pending) < 6 {
+                        thread::yield_now();
+                    }
+                    Err(HttpNotFound.into())
+                }).map_err(|e| find_cause::<HttpNotFound>(&e).is_some())
+            })
+        }).collect();
+        for handle in handles {
+            // Everyone waiting on the loader can still find its original error
+            assert_eq!(handle.join().unwrap(), Err(true));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // Once it's finished, we're free to try again
+        assert_eq!(loads.load(&1, || Ok(2)).unwrap(), 2);
+        // The loader gets the shared error too, so it needs to look through it
+        let error = loads.load(&2, || Err(HttpNotFound.into())).unwrap_err();
+        assert!(error.downcast_ref::<HttpNotFound>().is_none());
+        assert!(find_cause::<HttpNotFound>(&error).is_some());
+    }
+    #[test]
+    fn loading_cache() {
+        let cache = LoadingCache::<IndexMap<u32, u32>>::default();
+        assert_eq!(cache.load(&1, || Ok(1)).unwrap(), 1);
+        // Loaded values are reused, and failures aren't remembered
+        assert_eq!(cache.load(&1, || panic!("Loaded twice")).unwrap(), 1);
+        assert!(cache.load(&2, || Err(format_err!("Missing"))).is_err());
+        assert_eq!(cache.get(&2), None);
+        // Reloading replaces the existing value
+        assert_eq!(cache.reload(&1, || Ok(3)).unwrap(), 3);
+        assert_eq!(cache.load(&1, || panic!("Loaded twice")).unwrap(), 3);
+        cache.update(|values| { values.remove(&1); });
+        assert_eq!(cache.values().len(), 0);
+    }
+}
@@ -5,18 +5,126 @@ use std::str::FromStr;
 use serde::{Serializer, Serialize, Deserialize, Deserializer};
 use serde::ser::{SerializeStruct};
 use serde::de::{self, MapAccess, SeqAccess};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
 use failure_derive::Fail;
 
 
+/// A minecraft version, which may be a snapshot or pre-release
+///
+/// Versions are ordered by the release they lead up to,
+/// with snapshots first, then pre-releases, release candidates and finally the release itself.
+/// For snapshots, the release is determined by the week they were published.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct MinecraftVersion {
     pub major: u32,
     pub minor: u32,
-    pub patch: u32
+    pub patch: u32,
+    pub stage: ReleaseStage
 }
+/// How far along a minecraft version is towards its release
+///
+/// The declaration order is significant, since it determines the ordering of the versions.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseStage {
+    /// A weekly snapshot like `18w43a`, with its two-digit year
+    Snapshot {
+        year: u32,
+        week: u32,
+        build: char
+    },
+    PreRelease(u32),
+    ReleaseCandidate(u32),
+    Release
+}
+impl Default for ReleaseStage {
+    #[inline]
+    fn default() -> ReleaseStage {
+        ReleaseStage::Release
+    }
+}
+/// The weeks each release's snapshots were published in (inclusive),
+/// which are used to determine which release a snapshot leads up to.
+///
+/// We can't know which release a snapshot newer than all of these is for,
+/// so they're given `UNKNOWN_PATCH` instead (see `MinecraftVersion::snapshot`).
+const SNAPSHOT_WEEKS: [((u32, u32), (u32, u32), (u32, u32, u32)); 36] = [
+    ((11, 47), (12, 8), (1, 2, 0)),
+    ((12, 15), (12, 30), (1, 3, 0)),
+    ((12, 32), (12, 42), (1, 4, 0)),
+    ((12, 49), (12, 50), (1, 4, 6)),
+    ((13, 1), (13, 10), (1, 5, 0)),
+    ((13, 11), (13, 12), (1, 5, 1)),
+    ((13, 16), (13, 26), (1, 6, 0)),
+    ((13, 36), (13, 43), (1, 7, 0)),
+    ((13, 47), (13, 49), (1, 7, 4)),
+    ((14, 2), (14, 34), (1, 8, 0)),
+    ((15, 31), (16, 7), (1, 9, 0)),
+    ((16, 14), (16, 15), (1, 9, 3)),
+    ((16, 20), (16, 21), (1, 10, 0)),
+    ((16, 32), (16, 44), (1, 11, 0)),
+    ((16, 50), (16, 50), (1, 11, 1)),
+    ((17, 6), (17, 18), (1, 12, 0)),
+    ((17, 31), (17, 31), (1, 12, 1)),
+    ((17, 43), (18, 22), (1, 13, 0)),
+    ((18, 30), (18, 33), (1, 13, 1)),
+    ((18, 43), (19, 14), (1, 14, 0)),
+    ((19, 34), (19, 46), (1, 15, 0)),
+    ((20, 6), (20, 22), (1, 16, 0)),
+    ((20, 27), (20, 30), (1, 16, 2)),
+    ((20, 45), (21, 20), (1, 17, 0)),
+    ((21, 37), (21, 44), (1, 18, 0)),
+    ((22, 11), (22, 19), (1, 19, 0)),
+    ((22, 24), (22, 24), (1, 19, 1)),
+    ((22, 42), (22, 46), (1, 19, 3)),
+    ((23, 3), (23, 7), (1, 19, 4)),
+    ((23, 12), (23, 18), (1, 20, 0)),
+    ((23, 31), (23, 35), (1, 20, 2)),
+    ((23, 40), (23, 46), (1, 20, 3)),
+    ((24, 3), (24, 14), (1, 20, 5)),
+    ((24, 18), (24, 21), (1, 21, 0)),
+    ((24, 33), (24, 40), (1, 21, 2)),
+    ((24, 44), (24, 46), (1, 21, 4)),
+];
+/// The patch of snapshots that are newer than any in `SNAPSHOT_WEEKS`,
+/// which orders them after every release we know of.
+const UNKNOWN_PATCH: u32 = ::std::u32::MAX;
 impl MinecraftVersion {
+    #[inline]
+    pub const fn release(major: u32, minor: u32, patch: u32) -> MinecraftVersion {
+        MinecraftVersion { major, minor, patch, stage: ReleaseStage::Release }
+    }
+    /// The snapshot published in the specified week, like `18w43a`
+    ///
+    /// If it's newer than any snapshot we know the release of,
+    /// it's ordered after the latest release we know of, and then by when it was published.
+    pub fn snapshot(year: u32, week: u32, build: char) -> MinecraftVersion {
+        let published = (year, week);
+        let target = SNAPSHOT_WEEKS.iter()
+            .find(|&&(_, end, _)| published <= end)
+            .map(|&(_, _, target)| target)
+            .unwrap_or_else(|| {
+                let (major, minor, _) = SNAPSHOT_WEEKS[SNAPSHOT_WEEKS.len() - 1].2;
+                (major, minor, UNKNOWN_PATCH)
+            });
+        MinecraftVersion {
+            major: target.0, minor: target.1, patch: target.2,
+            stage: ReleaseStage::Snapshot { year, week, build }
+        }
+    }
+    #[inline]
+    pub fn is_release(&self) -> bool {
+        self.stage == ReleaseStage::Release
+    }
+    /// The release this version leads up to, which is itself if it's already a release
+    ///
+    /// Returns `None` for snapshots that are newer than any we know the release of.
+    #[inline]
+    pub fn target_release(&self) -> Option<MinecraftVersion> {
+        if self.patch == UNKNOWN_PATCH { return None }
+        Some(MinecraftVersion::release(self.major, self.minor, self.patch))
+    }
     #[inline]
     pub(crate) fn unknown(self) -> UnknownMinecraftVersion {
         UnknownMinecraftVersion(self)
@@ -24,6 +132,17 @@ impl MinecraftVersion {
     pub fn name(&self) -> String {
         let mut name = String::with_capacity(16);
         let mut buffer = ::itoa::Buffer::new();
+        if let ReleaseStage::Snapshot { year, week, build } = self.stage {
+            // Snapshots don't include the release in their name
+            name.push_str(buffer.format(year));
+            name.push('w');
+            if week < 10 {
+                name.push('0');
+            }
+            name.push_str(buffer.format(week));
+            name.push(build);
+            return name
+        }
         name.push_str(buffer.format(self.major));
         name.push('.');
         name.push_str(buffer.format(self.minor));
@@ -31,6 +150,17 @@ impl MinecraftVersion {
             name.push('.');
             name.push_str(buffer.format(self.patch));
         }
+        match self.stage {
+            ReleaseStage::PreRelease(number) => {
+                name.push_str("-pre");
+                name.push_str(buffer.format(number));
+            },
+            ReleaseStage::ReleaseCandidate(number) => {
+                name.push_str("-rc");
+                name.push_str(buffer.format(number));
+            },
+            ReleaseStage::Release | ReleaseStage::Snapshot { .. } => {}
+        }
         name
     }
 }
@@ -38,34 +168,72 @@ impl FromStr for MinecraftVersion {
     type Err = InvalidMinecraftVersion;
 
     fn from_str(s: &str) -> Result<Self, InvalidMinecraftVersion> {
-        let mut parts = s.split('.');
         let error = || InvalidMinecraftVersion(s.into());
+        if is_snapshot(s) {
+            return parse_snapshot(s).ok_or_else(error)
+        }
+        // Older pre-releases are named like `1.14 Pre-Release 1` instead of `1.14-pre1`
+        let (release, stage) = if let Some(index) = s.find(" Pre-Release ") {
+            let number = s[(index + 13)..].parse().map_err(|_| error())?;
+            (&s[..index], ReleaseStage::PreRelease(number))
+        } else if let Some(index) = s.find("-pre") {
+            let number = s[(index + 4)..].parse().map_err(|_| error())?;
+            (&s[..index], ReleaseStage::PreRelease(number))
+        } else if let Some(index) = s.find("-rc") {
+            let number = s[(index + 3)..].parse().map_err(|_| error())?;
+            (&s[..index], ReleaseStage::ReleaseCandidate(number))
+        } else {
+            (s, ReleaseStage::Release)
+        };
+        let mut parts = release.split('.');
         let major = parts.next()
             .and_then(|s| s.parse().ok())
             .ok_or_else(error)?;
         let minor = parts.next()
             .and_then(|s| s.parse().ok())
             .ok_or_else(error)?;
-        Ok(match parts.next() {
+        let patch = match parts.next() {
             Some(s) => {
                 let patch = s.parse().ok()
                     .ok_or_else(error)?;
                 if parts.next().is_some() {
                     return Err(error())
                 }
-                MinecraftVersion { major, minor, patch: patch }
+                patch
             }
-            None => MinecraftVersion { major, minor, patch: 0 }
-        })
+            None => 0
+        };
+        Ok(MinecraftVersion { major, minor, patch, stage })
     }
 }
+/// Check if the name has the form of a snapshot, `{year}w{week}{build}`
+#[inline]
+fn is_snapshot(s: &str) -> bool {
+    s.len() == 6 && s.as_bytes()[2] == b'w'
+}
+/// Parse a snapshot of the form `{year}w{week}{build}`, like `18w43a`
+fn parse_snapshot(s: &str) -> Option<MinecraftVersion> {
+    debug_assert!(is_snapshot(s));
+    let build = s[3..].chars().last()?;
+    if !build.is_ascii_lowercase() { return None }
+    let year = s.get(..2)?.parse().ok()?;
+    let week = s.get(3..5)?.parse().ok()?;
+    Some(MinecraftVersion::snapshot(year, week, build))
+}
 impl Display for MinecraftVersion {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let ReleaseStage::Snapshot { year, week, build } = self.stage {
+            return write!(f, "{:02}w{:02}{}", year, week, build)
+        }
         write!(f, "{}.{}", self.major, self.minor)?;
         if self.patch != 0 {
             write!(f, ".{}", self.patch)?;
         }
-        Ok(())
+        match self.stage {
+            ReleaseStage::PreRelease(number) => write!(f, "-pre{}", number),
+            ReleaseStage::ReleaseCandidate(number) => write!(f, "-rc{}", number),
+            ReleaseStage::Release | ReleaseStage::Snapshot { .. } => Ok(())
+        }
     }
 }
 impl Serialize for MinecraftVersion {
@@ -74,10 +242,11 @@ impl Serialize for MinecraftVersion {
         if serializer.is_human_readable() {
             serializer.serialize_str(&format!("{}", self))
         } else {
-            let mut s = serializer.serialize_struct("MinecraftVersion", 3)?;
+            let mut s = serializer.serialize_struct("MinecraftVersion", 4)?;
             s.serialize_field("major", &self.major)?;
             s.serialize_field("minor", &self.minor)?;
             s.serialize_field("patch", &self.patch)?;
+            s.serialize_field("stage", &self.stage)?;
             s.end()
         }
     }
@@ -88,7 +257,7 @@ impl<'de> Deserialize<'de> for MinecraftVersion {
         struct VersionVisitor;
         #[derive(Deserialize)]
         #[serde(field_identifier, rename_all = "lowercase")]
-        enum Field { Major, Minor, Patch }
+        enum Field { Major, Minor, Patch, Stage }
         impl<'de> ::serde::de::Visitor<'de> for VersionVisitor {
             type Value = MinecraftVersion;
 
@@ -109,7 +278,9 @@ impl<'de> Deserialize<'de> for MinecraftVersion {
                     .ok_or_else(|| de::Error::invalid_length(1, &self))?;
                 let patch = seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(2, &self))?;
-                Ok(MinecraftVersion { major, minor, patch })
+                // Versions serialized before we supported snapshots don't have a stage
+                let stage = seq.next_element()?.unwrap_or_default();
+                Ok(MinecraftVersion { major, minor, patch, stage })
             }
 
             fn visit_map<A>(self, mut map: A) -> Result<MinecraftVersion, A::Error> where
@@ -117,6 +288,7 @@ impl<'de> Deserialize<'de> for MinecraftVersion {
                 let mut major = None;
                 let mut minor = None;
                 let mut patch = None;
+                let mut stage = None;
                 while let Some(key) = map.next_key::<Field>()? {
                     match key {
                         Field::Major => {
@@ -137,20 +309,28 @@ impl<'de> Deserialize<'de> for MinecraftVersion {
                             }
                             patch = Some(map.next_value()?)
                         },
+                        Field::Stage => {
+                            if stage.is_some() {
+                                return Err(de::Error::duplicate_field("stage"))
+                            }
+                            stage = Some(map.next_value()?)
+                        },
                     }
                 }
                 let major = major.ok_or_else(|| de::Error::missing_field("major"))?;
                 let minor = minor.ok_or_else(|| de::Error::missing_field("minor"))?;
                 let patch = patch.ok_or_else(|| de::Error::missing_field("patch"))?;
-                Ok(MinecraftVersion { major, minor, patch })
+                let stage = stage.unwrap_or_default();
+                Ok(MinecraftVersion { major, minor, patch, stage })
             }
         }
         if deserializer.is_human_readable() {
-            deserializer.deserialize_str(VersionVisitor)
+            // We accept the struct form too, since that's how older versions were serialized
+            deserializer.deserialize_any(VersionVisitor)
         } else {
             deserializer.deserialize_struct(
                 "MinecraftVersion",
-                &["major", "minor", "patch"],
+                &["major", "minor", "patch", "stage"],
                 VersionVisitor
             )
         }
@@ -161,4 +341,61 @@ impl<'de> Deserialize<'de> for MinecraftVersion {
 pub struct InvalidMinecraftVersion(String);
 #[derive(Debug, Fail)]
 #[fail(display = "Unknown minecraft version {}", _0)]
-pub struct UnknownMinecraftVersion(MinecraftVersion);
\ No newline at end of file
+pub struct UnknownMinecraftVersion(MinecraftVersion);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn parse(s: &str) -> MinecraftVersion {
+        s.parse().unwrap()
+    }
+    #[test]
+    fn parse_versions() {
+        assert_eq!(parse("1.13"), MinecraftVersion::release(1, 13, 0));
+        assert_eq!(parse("1.13.2"), MinecraftVersion::release(1, 13, 2));
+        assert_eq!(parse("1.14-pre1").stage, ReleaseStage::PreRelease(1));
+        assert_eq!(parse("1.14 Pre-Release 2").stage, ReleaseStage::PreRelease(2));
+        assert_eq!(parse("1.13.2-rc1").stage, ReleaseStage::ReleaseCandidate(1));
+        assert_eq!(parse("18w43a").stage, ReleaseStage::Snapshot { year: 18, week: 43, build: 'a' });
+        for name in &["1.13", "1.13.2", "1.14-pre1", "1.13.2-rc1", "18w43a", "17w06a"] {
+            assert_eq!(format!("{}", parse(name)), *name);
+            assert_eq!(parse(name).name(), *name);
+        }
+        // We don't know which release the newest snapshots are for, but they still parse
+        assert_eq!(format!("{}", parse("25w10a")), "25w10a");
+        assert_eq!(parse("25w10a").target_release(), None);
+        assert_eq!(parse("18w43a").target_release(), Some(MinecraftVersion::release(1, 14, 0)));
+        for invalid in &["1", "1.13-pre", "18w43", "2000", "w43a", "18wxxa"] {
+            assert!(invalid.parse::<MinecraftVersion>().is_err(), "{}", invalid);
+        }
+    }
+    #[test]
+    fn version_ordering() {
+        let versions = [
+            "1.12.2", "17w43a", "18w22c", "1.13-pre1", "1.13-pre10",
+            "1.13", "18w30a", "1.13.1-rc1", "1.13.1", "1.13.2", "18w43a", "1.14-pre1", "1.14",
+            "1.14.4", "19w34a", "1.15", "1.21.4", "25w02a", "25w10a", "25w10b", "26w01a", "1.22"
+        ];
+        for pair in versions.windows(2) {
+            assert!(parse(pair[0]) < parse(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+    }
+    #[test]
+    fn deserialize_versions() {
+        let deserialize = |json: &str| -> MinecraftVersion { ::serde_json::from_str(json).unwrap() };
+        assert_eq!(deserialize(r#""1.13.2""#), parse("1.13.2"));
+        assert_eq!(deserialize(r#""18w43a""#), parse("18w43a"));
+        assert_eq!(
+            deserialize(r#"{"major": 1, "minor": 14, "patch": 0, "stage": {"pre_release": 1}}"#),
+            parse("1.14-pre1")
+        );
+        assert_eq!(
+            deserialize(r#"{"major": 1, "minor": 14, "patch": 0, "stage": {"snapshot": {"year": 18, "week": 43, "build": "a"}}}"#),
+            parse("18w43a")
+        );
+        // Versions serialized before we supported snapshots don't have a stage
+        assert_eq!(deserialize(r#"{"major": 1, "minor": 13, "patch": 2}"#), parse("1.13.2"));
+        assert_eq!(::serde_json::to_string(&parse("18w43a")).unwrap(), r#""18w43a""#);
+        assert_eq!(deserialize(r#""25w10a""#), parse("25w10a"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Pre-fetches many versions into the cache at once.
+//!
+//! Warming only downloads data to disk, without loading it into memory,
+//! so it doesn't need to hold the loader locks and can run in parallel.
+use std::fmt::{self, Display, Formatter};
+
+use failure::Error;
+use parking_lot::Mutex;
+
+use crate::MinecraftVersion;
+use crate::mcp::McpVersionSpec;
+use crate::cache::MinecraftMappingsCache;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WarmTask {
+    Srg(MinecraftVersion),
+    Mcp(McpVersionSpec),
+    Spigot(MinecraftVersion),
+}
+impl Display for WarmTask {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            WarmTask::Srg(version) => write!(f, "srg {}", version),
+            WarmTask::Mcp(spec) => write!(f, "mcp {}", spec),
+            WarmTask::Spigot(version) => write!(f, "spigot {}", version),
+        }
+    }
+}
+
+/// The progress of an ongoing warm operation, reported after each task finishes
+pub struct WarmProgress<'a> {
+    pub task: WarmTask,
+    pub result: Result<(), &'a Error>,
+    /// The number of tasks that have finished, including this one
+    pub completed: usize,
+    pub total: usize
+}
+
+#[derive(Debug, Default)]
+pub struct WarmReport {
+    pub succeeded: Vec<WarmTask>,
+    pub failed: Vec<(WarmTask, Error)>
+}
+
+pub(crate) fn warm<F>(
+    cache: &MinecraftMappingsCache,
+    mut tasks: Vec<WarmTask>,
+    workers: usize,
+    progress: F
+) -> WarmReport where F: Fn(WarmProgress) + Sync {
+    // Remove duplicates, while preserving the original order
+    let mut seen = ::indexmap::IndexSet::with_capacity(tasks.len());
+    tasks.retain(|task| seen.insert(*task));
+    let total = tasks.len();
+    let queue = Mutex::new(tasks.into_iter());
+    let report = Mutex::new(WarmReport::default());
+    let workers = workers.max(1).min(total.max(1));
+    ::crossbeam::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let task = match queue.lock().next() {
+                        Some(task) => task,
+                        None => break
+                    };
+                    let result = cache.fetch(task);
+                    let mut report = report.lock();
+                    let completed = report.succeeded.len() + report.failed.len() + 1;
+                    progress(WarmProgress {
+                        task, completed, total,
+                        result: result.as_ref().map(|_| ())
+                    });
+                    match result {
+                        Ok(()) => report.succeeded.push(task),
+                        Err(e) => report.failed.push((task, e))
+                    }
+                }
+            });
+        }
+    });
+    report.into_inner()
+}
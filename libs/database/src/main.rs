@@ -8,7 +8,7 @@ use std::process::exit;
 use minecraft_mappings_database::{DatabaseLocation, MappingsDatabase};
 use mappings::MinecraftVersion;
 
-const MINECRAFT_VERSION: MinecraftVersion = MinecraftVersion { major: 1, minor: 13, patch: 0 };
+const MINECRAFT_VERSION: MinecraftVersion = MinecraftVersion::release(1, 13, 0);
 
 fn main() {
     // TODO: Redo all this with clap
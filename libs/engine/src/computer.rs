@@ -1,13 +1,19 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
 
 use indexmap::{IndexMap};
 use failure::{Error, format_err};
 use failure_derive::Fail;
 use mappings::cache::MinecraftMappingsCache;
-use mappings::{MinecraftVersion, McpVersion};
+use mappings::mapped::MappedMappings;
+use mappings::maintenance::CacheEntryKind;
+use mappings::access::{AccessTransform, AccessTarget};
+use mappings::spigot::{SpigotMappings, SERVER_PACKAGE};
+use mappings::{MinecraftVersion, McpVersionSpec, SpigotRevision, YarnVersion};
 use srglib::prelude::*;
 
 use super::target::{TargetMapping, TargetFilter, MappingSystem};
+use super::metadata::{TargetMetadata, MemberMetadata};
 
 // These are the 'basic' mappings that we use as the basis for computing all others
 const OBF2SRG: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::Srg);
@@ -18,26 +24,128 @@ const SRG2OBF: TargetMapping = OBF2SRG.reversed();
 const OBF2MCP: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::Mcp);
 const MCP2OBF: TargetMapping = OBF2MCP.reversed();
 const SPIGOT2OBF: TargetMapping = OBF2SPIGOT.reversed();
+const OBF2CRAFTBUKKIT: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::CraftBukkit);
+const CRAFTBUKKIT2OBF: TargetMapping = OBF2CRAFTBUKKIT.reversed();
+const OBF2MOJANG: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::Mojang);
+const MOJANG2OBF: TargetMapping = OBF2MOJANG.reversed();
+const OBF2INTERMEDIARY: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::Intermediary);
+const INTERMEDIARY2OBF: TargetMapping = OBF2INTERMEDIARY.reversed();
+const INTERMEDIARY2YARN: TargetMapping = TargetMapping::new(MappingSystem::Intermediary, MappingSystem::Yarn);
+const OBF2YARN: TargetMapping = TargetMapping::new(MappingSystem::Obf, MappingSystem::Yarn);
+const YARN2OBF: TargetMapping = OBF2YARN.reversed();
 
 pub struct MappingsTargetComputer<'a> {
     cache: &'a MinecraftMappingsCache,
     minecraft_version: MinecraftVersion,
-    mcp_version: Option<McpVersion>,
+    mcp_version: Option<McpVersionSpec>,
+    cross_version_mcp: bool,
+    spigot_revision: Option<SpigotRevision>,
+    yarn_version: Option<YarnVersion>,
     computed_targets: RefCell<IndexMap<TargetMapping, FrozenMappings>>,
+    missing_mcp_names: Cell<Option<usize>>,
 }
 impl<'a> MappingsTargetComputer<'a> {
     pub fn new(
         cache: &'a MinecraftMappingsCache,
         minecraft_version: MinecraftVersion,
-        mcp_version: Option<McpVersion>
+        mcp_version: Option<McpVersionSpec>
     ) -> Self {
-        MappingsTargetComputer { cache, minecraft_version, mcp_version, computed_targets: Default::default() }
+        MappingsTargetComputer {
+            cache, minecraft_version, mcp_version,
+            cross_version_mcp: false,
+            spigot_revision: None,
+            yarn_version: None,
+            computed_targets: Default::default(),
+            missing_mcp_names: Cell::new(None)
+        }
+    }
+    /// Allow using a MCP version that was exported for a different minecraft version
+    ///
+    /// This is useful for patch releases that never got their own MCP exports,
+    /// but any srg names that changed between the versions will be left unmapped.
+    /// Use `missing_mcp_names` to check how many there were.
+    #[inline]
+    pub fn cross_version_mcp(mut self, allowed: bool) -> Self {
+        self.cross_version_mcp = allowed;
+        self
     }
+    /// Use a specific revision of spigot's mappings, instead of the latest one for the minecraft version
+    ///
+    /// The revision must still be for the same minecraft version.
     #[inline]
-    fn mcp_version(&self) -> Result<McpVersion, Error> {
-        self.mcp_version.ok_or_else(|| format_err!("Unspecified MCP version"))
+    pub fn spigot_revision(mut self, revision: Option<SpigotRevision>) -> Self {
+        self.spigot_revision = revision;
+        self
+    }
+    /// Use the specified build of yarn, which must be for the same minecraft version
+    #[inline]
+    pub fn yarn_version(mut self, version: Option<YarnVersion>) -> Self {
+        self.yarn_version = version;
+        self
+    }
+    /// The number of srg fields and methods that had no MCP name,
+    /// or `None` if we haven't computed any MCP names yet.
+    #[inline]
+    pub fn missing_mcp_names(&self) -> Option<usize> {
+        self.missing_mcp_names.get()
+    }
+    /// Count the srg fields and methods that have no MCP name,
+    /// computing the MCP names if we haven't already.
+    ///
+    /// Unlike `missing_mcp_names`, this also works if the targets were loaded from their mapped stores.
+    pub fn count_missing_mcp_names(&self) -> Result<usize, Error> {
+        if let Some(missing) = self.missing_mcp_names.get() {
+            return Ok(missing)
+        }
+        self.compute_target(SRG2MCP)?;
+        Ok(self.missing_mcp_names.get().expect("Didn't count missing MCP names"))
+    }
+    fn mcp_version(&self) -> Result<McpVersionSpec, Error> {
+        let spec = self.mcp_version.ok_or_else(|| format_err!("Unspecified MCP version"))?;
+        if !self.cross_version_mcp {
+            self.cache.check_mcp_version(spec.version, self.minecraft_version)?;
+        }
+        Ok(spec)
+    }
+    fn checked_yarn_version(&self) -> Result<YarnVersion, Error> {
+        let version = self.yarn_version.ok_or_else(|| format_err!("Unspecified yarn version"))?;
+        if version.minecraft_version != self.minecraft_version {
+            return Err(IncompatibleYarnVersion { version, minecraft_version: self.minecraft_version }.into())
+        }
+        Ok(version)
+    }
+    fn spigot_mappings(&self) -> Result<Arc<SpigotMappings>, Error> {
+        match self.spigot_revision {
+            Some(ref revision) => {
+                let mappings = self.cache.load_spigot_revision(revision)?;
+                if mappings.minecraft_version != self.minecraft_version {
+                    return Err(IncompatibleSpigotRevision {
+                        revision: revision.clone(),
+                        revision_version: mappings.minecraft_version,
+                        minecraft_version: self.minecraft_version
+                    }.into())
+                }
+                Ok(mappings)
+            },
+            None => self.cache.load_spigot_mappings(self.minecraft_version)
+        }
+    }
+    fn craftbukkit_package(&self) -> Result<String, Error> {
+        match self.spigot_revision {
+            Some(ref revision) => self.cache.load_craftbukkit_package(revision),
+            None => self.cache.load_craftbukkit_package(&SpigotRevision::Latest(self.minecraft_version))
+        }
+    }
+    /// Check we were given everything the target needs, before we start computing anything
+    fn check_target(&self, target: TargetMapping) -> Result<(), Error> {
+        if target.flags.side().is_some() && self.mcp_version.is_none() {
+            // We need MCP to know the sides, even if the target has nothing to do with MCP
+            return Err(SideNeedsMcpVersion(target).into())
+        }
+        Ok(())
     }
     pub fn compute_target(&self, target: TargetMapping) -> Result<FrozenMappings, Error> {
+        self.check_target(target)?;
         {
             let computed_targets =
                 self.computed_targets.borrow();
@@ -51,6 +159,176 @@ impl<'a> MappingsTargetComputer<'a> {
         self.computed_targets.borrow_mut().insert(target, mappings.clone());
         Ok(mappings)
     }
+    /// Compute the specified target as a memory-mapped store,
+    /// which is persisted in the cache and reused by later processes.
+    ///
+    /// This is intended for long running servers,
+    /// since the mappings don't need to be kept on the heap.
+    pub fn compute_mapped_target(&self, target: TargetMapping) -> Result<Arc<MappedMappings>, Error> {
+        self.check_target(target)?;
+        let mut name = match self.mcp_version {
+            Some(mcp_version) if target.needs_mcp_version() => {
+                /*
+                 * The documented and undocumented exports are cached separately,
+                 * so they're separate inputs even though they have the same names.
+                 */
+                format!("{}-{}", target, mcp_version)
+            },
+            _ => format!("{}", target)
+        };
+        if self.spigot_revision.is_some() && target.needs_spigot() {
+            name.push_str(&format!("-spigot{}", self.spigot_mappings()?.build_data_commit));
+        }
+        if target.needs_yarn_version() {
+            name.push_str(&format!("-yarn{}", self.checked_yarn_version()?.build));
+        }
+        let location = self.cache.targets_location(self.minecraft_version)
+            .join(format!("{}.mapped", name));
+        self.cache.load_mapped(
+            location,
+            || self.input_fingerprint(target),
+            || self.compute_target(target)
+        )
+    }
+    /// Identify the cached data the target is computed from,
+    /// or `None` if some of it hasn't been cached yet.
+    ///
+    /// Anything that can change underneath us (like the latest spigot revision or imported mojang mappings)
+    /// changes the fingerprint, so we never serve a stale mapped store.
+    fn input_fingerprint(&self, target: TargetMapping) -> Result<Option<String>, Error> {
+        let version = self.minecraft_version;
+        let mut fingerprint = format!("{}", target);
+        let mut inputs = Vec::new();
+        if target.needs_mcp_version() {
+            inputs.push(CacheEntryKind::Srg(version));
+            inputs.push(CacheEntryKind::Mcp(self.mcp_version()?));
+        }
+        for &system in &[target.original, target.renamed] {
+            match system {
+                MappingSystem::Srg => inputs.push(CacheEntryKind::Srg(version)),
+                MappingSystem::Spigot | MappingSystem::CraftBukkit => {
+                    let spigot_mappings = self.spigot_mappings()?;
+                    fingerprint.push_str(&format!("\nspigot {}", spigot_mappings.build_data_commit));
+                    if system == MappingSystem::CraftBukkit {
+                        fingerprint.push_str(&format!("\ncraftbukkit {}", self.craftbukkit_package()?));
+                    }
+                    inputs.push(CacheEntryKind::Spigot(spigot_mappings.build_data_commit.clone(), None));
+                },
+                MappingSystem::Mojang => inputs.push(CacheEntryKind::Mojang(version)),
+                MappingSystem::Intermediary => inputs.push(CacheEntryKind::Intermediary(version)),
+                MappingSystem::Yarn => {
+                    inputs.push(CacheEntryKind::Intermediary(version));
+                    inputs.push(CacheEntryKind::Yarn(self.checked_yarn_version()?));
+                },
+                MappingSystem::Mcp | MappingSystem::Obf => {},
+            }
+        }
+        let directory = self.cache.directory();
+        for input in &inputs {
+            match directory.fingerprint(input) {
+                Some(input_fingerprint) => {
+                    fingerprint.push('\n');
+                    fingerprint.push_str(&input_fingerprint);
+                },
+                None => return Ok(None)
+            }
+        }
+        Ok(Some(fingerprint))
+    }
+    /// Compute the information about the target's members that can't be represented in SRG,
+    /// like the MCP documentation and parameter names.
+    ///
+    /// The members are identified by their renamed names, so this lines up with the computed target.
+    /// Targets that don't involve MCP have no metadata.
+    pub fn compute_metadata(&self, target: TargetMapping) -> Result<TargetMetadata, Error> {
+        let mut metadata = TargetMetadata::default();
+        if !target.needs_mcp_version() { return Ok(metadata) }
+        let mcp_mappings = self.cache.load_mcp_mappings(self.mcp_version()?)?;
+        let mappings = self.compute_target(target)?;
+        let original2srg = self.original2srg(target.original)?;
+        for (original, renamed) in mappings.fields() {
+            let srg_name = srg_field_name(original2srg.as_ref(), &original);
+            if let Some(doc) = mcp_mappings.doc(&srg_name) {
+                metadata.fields.insert(
+                    format!("{}/{}", renamed.declaring_type().internal_name(), renamed.name),
+                    MemberMetadata { doc: Some(doc.into()), ..Default::default() }
+                );
+            }
+        }
+        for (original, renamed) in mappings.methods() {
+            let srg_name = srg_method_name(original2srg.as_ref(), &original);
+            let member = MemberMetadata {
+                doc: mcp_mappings.doc(&srg_name).map(String::from),
+                parameters: mcp_mappings.method_params(&srg_name).iter().cloned().collect()
+            };
+            if member.doc.is_some() || !member.parameters.is_empty() {
+                metadata.methods.insert(
+                    format!(
+                        "{}/{} {}",
+                        renamed.declaring_type().internal_name(),
+                        renamed.name,
+                        renamed.signature().descriptor()
+                    ),
+                    member
+                );
+            }
+        }
+        Ok(metadata)
+    }
+    /// Translate spigot's access transforms into the specified mapping system
+    ///
+    /// Anything we don't have a mapping for keeps its spigot name.
+    pub fn compute_access_transforms(&self, renamed: MappingSystem) -> Result<Vec<AccessTransform>, Error> {
+        let spigot_mappings = self.spigot_mappings()?;
+        if renamed == MappingSystem::Spigot {
+            return Ok(spigot_mappings.access_transforms.clone())
+        }
+        let mappings = self.compute_target(MappingSystem::Spigot.create_target(renamed))?;
+        Ok(spigot_mappings.access_transforms.iter().map(|transform| {
+            let target = match transform.target {
+                AccessTarget::Class(ref class) => AccessTarget::Class(remap_class(&mappings, class)),
+                AccessTarget::Field { ref class, ref name } => {
+                    let original = FieldData::new(
+                        name.as_str().into(),
+                        ReferenceType::from_internal_name(class)
+                    );
+                    AccessTarget::Field {
+                        class: remap_class(&mappings, class),
+                        name: match mappings.get_remapped_field(&original) {
+                            Some(renamed) => String::from(&*renamed.name),
+                            None => name.clone()
+                        }
+                    }
+                },
+                AccessTarget::Method { ref class, ref name, ref descriptor } => {
+                    let original = MethodData::new(
+                        name.as_str().into(),
+                        ReferenceType::from_internal_name(class),
+                        MethodSignature::from_descriptor(descriptor)
+                    );
+                    AccessTarget::Method {
+                        class: remap_class(&mappings, class),
+                        name: match mappings.get_remapped_method(&original) {
+                            Some(renamed) => String::from(&*renamed.name),
+                            None => name.clone()
+                        },
+                        descriptor: remap_descriptor(&mappings, descriptor)
+                    }
+                },
+            };
+            AccessTransform { change: transform.change, target }
+        }).collect())
+    }
+    /// The mappings from the specified system into srg names, or `None` if it's already srg
+    ///
+    /// We need the srg names of members to look up anything about them in MCP.
+    fn original2srg(&self, original: MappingSystem) -> Result<Option<FrozenMappings>, Error> {
+        if original == MappingSystem::Srg {
+            Ok(None)
+        } else {
+            Ok(Some(self.compute_target(original.create_target(MappingSystem::Srg))?))
+        }
+    }
     fn fallback_compute_target(&self, target: TargetMapping) -> Result<FrozenMappings, Error> {
         // NOTE: These relationships are currently hardcoded
         let mut mappings = match (target.original, target.renamed) {
@@ -59,17 +337,23 @@ impl<'a> MappingsTargetComputer<'a> {
                 let mcp_version = self.mcp_version()?;
                 let mcp_mappings = self.cache.load_mcp_mappings(mcp_version)?;
                 let mut builder = SimpleMappings::default();
+                let mut missing = 0;
                 // NOTE: Serage already has the class names
                 for (_, serage) in obf2srg.fields() {
                     if let Some(mcp) = mcp_mappings.fields.get(&serage.name) {
                         builder.set_field_name(serage.clone(), mcp.clone());
+                    } else if serage.name.starts_with("field_") {
+                        missing += 1;
                     }
                 }
                 for (_, serage) in obf2srg.methods() {
                     if let Some(mcp) = mcp_mappings.methods.get(&serage.name) {
                         builder.set_method_name(serage.clone(), mcp.clone());
+                    } else if serage.name.starts_with("func_") {
+                        missing += 1;
                     }
                 }
+                self.missing_mcp_names.set(Some(missing));
                 builder.frozen()
             },
             (MappingSystem::Srg, MappingSystem::Spigot) => {
@@ -113,9 +397,89 @@ impl<'a> MappingsTargetComputer<'a> {
                 obf2srg.chain(srg2mcp)
             },
             (MappingSystem::Obf, MappingSystem::Spigot) => {
-                self.cache.load_spigot_mappings(self.minecraft_version)?
-                    .chained_mappings.clone()
+                self.spigot_mappings()?.chained_mappings.clone()
             }
+            (MappingSystem::Obf, MappingSystem::CraftBukkit) => {
+                let spigot_mappings = self.spigot_mappings()?;
+                let craftbukkit_package = self.craftbukkit_package()?;
+                // Only the server package itself is versioned, not any subpackages
+                spigot_mappings.chained_mappings.clone().transform_packages(|package| {
+                    if package == SERVER_PACKAGE { Some(craftbukkit_package.clone()) } else { None }
+                })
+            },
+            (MappingSystem::CraftBukkit, MappingSystem::CraftBukkit) |
+            (MappingSystem::Mojang, MappingSystem::Mojang) |
+            (MappingSystem::Intermediary, MappingSystem::Intermediary) |
+            (MappingSystem::Yarn, MappingSystem::Yarn) => panic!("Redundant"),
+            (MappingSystem::Obf, MappingSystem::Mojang) => {
+                self.cache.load_mojang_mappings(self.minecraft_version)?
+            },
+            (MappingSystem::Mojang, MappingSystem::Obf) => {
+                self.compute_target(OBF2MOJANG)?.inverted()
+            },
+            (MappingSystem::Mojang, renamed) => {
+                let mojang2obf = self.compute_target(MOJANG2OBF)?;
+                let obf2renamed = self.compute_target(MappingSystem::Obf.create_target(renamed))?;
+                mojang2obf.chain(obf2renamed)
+            },
+            (original, MappingSystem::Mojang) => {
+                let original2obf = self.compute_target(original.create_target(MappingSystem::Obf))?;
+                let obf2mojang = self.compute_target(OBF2MOJANG)?;
+                original2obf.chain(obf2mojang)
+            },
+            (MappingSystem::Obf, MappingSystem::Intermediary) => {
+                self.cache.load_intermediary_mappings(self.minecraft_version)?
+            },
+            (MappingSystem::Intermediary, MappingSystem::Obf) => {
+                self.compute_target(OBF2INTERMEDIARY)?.inverted()
+            },
+            (MappingSystem::Intermediary, MappingSystem::Yarn) => {
+                self.cache.load_yarn_mappings(self.checked_yarn_version()?)?
+            },
+            (MappingSystem::Yarn, MappingSystem::Intermediary) => {
+                self.compute_target(INTERMEDIARY2YARN)?.inverted()
+            },
+            (MappingSystem::Obf, MappingSystem::Yarn) => {
+                let obf2intermediary = self.compute_target(OBF2INTERMEDIARY)?;
+                let intermediary2yarn = self.compute_target(INTERMEDIARY2YARN)?;
+                obf2intermediary.chain(intermediary2yarn)
+            },
+            (MappingSystem::Yarn, MappingSystem::Obf) => {
+                self.compute_target(OBF2YARN)?.inverted()
+            },
+            (MappingSystem::Intermediary, renamed) => {
+                let intermediary2obf = self.compute_target(INTERMEDIARY2OBF)?;
+                let obf2renamed = self.compute_target(MappingSystem::Obf.create_target(renamed))?;
+                intermediary2obf.chain(obf2renamed)
+            },
+            (original, MappingSystem::Intermediary) => {
+                let original2obf = self.compute_target(original.create_target(MappingSystem::Obf))?;
+                let obf2intermediary = self.compute_target(OBF2INTERMEDIARY)?;
+                original2obf.chain(obf2intermediary)
+            },
+            (MappingSystem::Yarn, renamed) => {
+                let yarn2obf = self.compute_target(YARN2OBF)?;
+                let obf2renamed = self.compute_target(MappingSystem::Obf.create_target(renamed))?;
+                yarn2obf.chain(obf2renamed)
+            },
+            (original, MappingSystem::Yarn) => {
+                let original2obf = self.compute_target(original.create_target(MappingSystem::Obf))?;
+                let obf2yarn = self.compute_target(OBF2YARN)?;
+                original2obf.chain(obf2yarn)
+            },
+            (MappingSystem::CraftBukkit, MappingSystem::Obf) => {
+                self.compute_target(OBF2CRAFTBUKKIT)?.inverted()
+            },
+            (MappingSystem::CraftBukkit, renamed) => {
+                let craftbukkit2obf = self.compute_target(CRAFTBUKKIT2OBF)?;
+                let obf2renamed = self.compute_target(MappingSystem::Obf.create_target(renamed))?;
+                craftbukkit2obf.chain(obf2renamed)
+            },
+            (original, MappingSystem::CraftBukkit) => {
+                let original2obf = self.compute_target(original.create_target(MappingSystem::Obf))?;
+                let obf2craftbukkit = self.compute_target(OBF2CRAFTBUKKIT)?;
+                original2obf.chain(obf2craftbukkit)
+            },
             (MappingSystem::Srg, MappingSystem::Srg) |
             (MappingSystem::Mcp, MappingSystem::Mcp) |
             (MappingSystem::Spigot, MappingSystem::Spigot) |
@@ -174,6 +538,41 @@ impl<'a> MappingsTargetComputer<'a> {
                 *mappings = builder.frozen();
             }
         }
+        if let Some(side) = target.flags.side() {
+            let mcp_mappings = self.cache.load_mcp_mappings(self.mcp_version()?)?;
+            let original2srg = self.original2srg(target.original)?;
+            /*
+             * Members without any MCP information are kept, since we don't know their side.
+             * Classes are only dropped if all the members we know the side of are on the other side,
+             * so we track whether each class has any members on our side.
+             */
+            let mut class_sides: IndexMap<String, bool> = IndexMap::new();
+            for (original, _) in mappings.fields() {
+                if let Some(member_side) = mcp_mappings.side(&srg_field_name(original2srg.as_ref(), original)) {
+                    *class_sides.entry(String::from(&*original.declaring_type().internal_name()))
+                        .or_insert(false) |= side.includes(member_side);
+                }
+            }
+            for (original, _) in mappings.methods() {
+                if let Some(member_side) = mcp_mappings.side(&srg_method_name(original2srg.as_ref(), original)) {
+                    *class_sides.entry(String::from(&*original.declaring_type().internal_name()))
+                        .or_insert(false) |= side.includes(member_side);
+                }
+            }
+            let mut builder = mappings.rebuild();
+            builder.retain_classes(|original, _| {
+                class_sides.get(&*original.internal_name()).map_or(true, |&included| included)
+            });
+            builder.retain_fields(|original, _| {
+                mcp_mappings.side(&srg_field_name(original2srg.as_ref(), original))
+                    .map_or(true, |member_side| side.includes(member_side))
+            });
+            builder.retain_methods(|original, _| {
+                mcp_mappings.side(&srg_method_name(original2srg.as_ref(), original))
+                    .map_or(true, |member_side| side.includes(member_side))
+            });
+            *mappings = builder.frozen();
+        }
         match target.flags.filter() {
             None => {},
             Some(TargetFilter::Classes) => {
@@ -191,6 +590,54 @@ impl<'a> MappingsTargetComputer<'a> {
         Ok(())
     }
 }
+fn remap_class(mappings: &FrozenMappings, class: &str) -> String {
+    match mappings.get_remapped_class(&ReferenceType::from_internal_name(class)) {
+        Some(renamed) => String::from(&*renamed.internal_name()),
+        None => class.into()
+    }
+}
+/// Remap the class names in the specified descriptor
+fn remap_descriptor(mappings: &FrozenMappings, descriptor: &str) -> String {
+    let mut result = String::with_capacity(descriptor.len());
+    let mut remaining = descriptor;
+    while let Some(start) = remaining.find('L') {
+        result.push_str(&remaining[..=start]);
+        remaining = &remaining[(start + 1)..];
+        let end = remaining.find(';').unwrap_or_else(|| remaining.len());
+        result.push_str(&remap_class(mappings, &remaining[..end]));
+        remaining = &remaining[end..];
+    }
+    result.push_str(remaining);
+    result
+}
+fn srg_field_name(original2srg: Option<&FrozenMappings>, original: &FieldData) -> String {
+    match original2srg.and_then(|mappings| mappings.get_remapped_field(original)) {
+        Some(srg) => String::from(&*srg.name),
+        None => String::from(&*original.name)
+    }
+}
+fn srg_method_name(original2srg: Option<&FrozenMappings>, original: &MethodData) -> String {
+    match original2srg.and_then(|mappings| mappings.get_remapped_method(original)) {
+        Some(srg) => String::from(&*srg.name),
+        None => String::from(&*original.name)
+    }
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Spigot revision {} is for minecraft {}, not {}", revision, revision_version, minecraft_version)]
+pub struct IncompatibleSpigotRevision {
+    pub revision: SpigotRevision,
+    pub revision_version: MinecraftVersion,
+    pub minecraft_version: MinecraftVersion
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Yarn {} isn't for minecraft {}", version, minecraft_version)]
+pub struct IncompatibleYarnVersion {
+    pub version: YarnVersion,
+    pub minecraft_version: MinecraftVersion
+}
+#[derive(Debug, Fail)]
+#[fail(display = "Target {} needs an MCP version to know which side members are on", _0)]
+pub struct SideNeedsMcpVersion(pub TargetMapping);
 #[derive(Debug, Fail)]
 #[fail(display = "Unable to compute {}: {}", target, cause)]
 pub struct TargetComputeError {
@@ -0,0 +1,67 @@
+use std::io::Write;
+use std::str::FromStr;
+use std::fmt::{self, Display, Formatter};
+
+use failure::Error;
+use failure_derive::Fail;
+use serde_derive::{Deserialize, Serialize};
+use mappings::mapped::MappedMappings;
+use srglib::prelude::*;
+
+/// The formats we can output the computed targets in
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Srg,
+    Tsrg
+}
+impl OutputFormat {
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Srg => "srg",
+            OutputFormat::Tsrg => "tsrg",
+        }
+    }
+    pub fn write<W: Write>(self, mappings: &FrozenMappings, output: W) -> Result<(), Error> {
+        match self {
+            OutputFormat::Srg => SrgMappingsFormat::write(mappings, output)?,
+            OutputFormat::Tsrg => TabSrgMappingsFormat::write(mappings, output)?,
+        }
+        Ok(())
+    }
+    /// Write the mapped store, without materializing the mappings
+    pub fn write_mapped<W: Write>(self, mappings: &MappedMappings, output: W) -> Result<(), Error> {
+        match self {
+            OutputFormat::Srg => mappings.write_srg(output),
+            OutputFormat::Tsrg => mappings.write_tsrg(output),
+        }
+    }
+}
+impl Default for OutputFormat {
+    #[inline]
+    fn default() -> Self {
+        OutputFormat::Srg
+    }
+}
+impl FromStr for OutputFormat {
+    type Err = InvalidOutputFormat;
+
+    fn from_str(s: &str) -> Result<OutputFormat, InvalidOutputFormat> {
+        Ok(match s {
+            "srg" => OutputFormat::Srg,
+            "tsrg" => OutputFormat::Tsrg,
+            _ => return Err(InvalidOutputFormat(s.into()))
+        })
+    }
+}
+impl Display for OutputFormat {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid output format {:?}", _0)]
+pub struct InvalidOutputFormat(String);
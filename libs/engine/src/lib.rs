@@ -5,11 +5,19 @@
 //! - `spigot` - Spigot's deobfuscation mappings, held in the `BuildData` git repo
 //!   - These are significantly lower quality than the MCP mappings, and most member names are still obfuscated
 //!   - These mappings don't change very often, since plugins use them and would break if they changed
+//! - `craftbukkit` - The spigot names, with the server classes in CraftBukkit's versioned package
+//!   - This is what's actually loaded at runtime, like `net/minecraft/server/v1_13_R2/World`
+//!   - The package is determined by the CraftBukkit pom for the version
+//! - `mojang` - Mojang's official names, from the ProGuard mappings published since 1.14.4
+//!   - These can also be imported from local files, for versions missing from the version manifest
+//! - `intermediary` - Fabric's intermediary names, which stay the same across minecraft versions
+//! - `yarn` - Fabric's crowd sourced deobfuscated names, which are mapped from the intermediary names
+//!   - These have many builds for each minecraft version, which default to the latest one
 //! - `obf` - The obfuscated mojang names, which are internally used to unify the different mappings systems
 //!
 //! Mapping targets have a string representation of the form `{original}2{renamed}-{flags}-{minecraft_version}` with an optional modifier at the end.
 //! For example, `spigot2mcp` specifies mappings from the spigot names into the MCP names.
-//! Three modifiers are supported:
+//! Five modifiers are supported:
 //! - `classes` - Restricts the mappings to just class names.
 //! - `members` - Restricts the mappings to just member names.
 //! - `onlyobf` - Restricts the mappings to just names that are still obfuscated.
@@ -20,12 +28,20 @@
 //!     without changing names spigot already deobfuscated.
 //!   - I know I have personally become familiar with the spigot naming scheme
 //!      but I still want to take advantage of MCP naming information where spigot is lacking.
+//! - `client`/`server` - Restricts the mappings to members present on that side of the game.
+//!   - This relies on the side information from MCP, so it requires a MCP version even for other mapping systems.
+//!   - Classes are dropped if all their members MCP knows the side of are on the other side.
+//!   - Classes and members MCP doesn't know about are always kept.
 #![feature(min_const_fn)]
 #[cfg(dummy)]
 extern crate minecraft_mappings_core as mappings;
 
 mod target;
 mod computer;
+mod metadata;
+mod format;
 
-pub use self::target::{TargetMapping, MappingSystem};
+pub use self::target::{TargetMapping, TargetSide, MappingSystem};
 pub use self::computer::MappingsTargetComputer;
+pub use self::metadata::{TargetMetadata, MemberMetadata};
+pub use self::format::OutputFormat;
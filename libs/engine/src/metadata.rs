@@ -0,0 +1,27 @@
+use indexmap::IndexMap;
+use serde_derive::Serialize;
+
+/// Information about the members of a target, which can't be represented in SRG
+///
+/// Fields are keyed by `class/name` and methods by `class/name descriptor`,
+/// using the renamed names of the target.
+#[derive(Debug, Default, Serialize)]
+pub struct TargetMetadata {
+    pub fields: IndexMap<String, MemberMetadata>,
+    pub methods: IndexMap<String, MemberMetadata>
+}
+impl TargetMetadata {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.methods.is_empty()
+    }
+}
+#[derive(Debug, Default, Serialize)]
+pub struct MemberMetadata {
+    /// The javadoc of the member
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    /// The names of the method's parameters, keyed by their local variable index
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub parameters: IndexMap<u32, String>
+}
@@ -5,6 +5,7 @@ use failure_derive::Fail;
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 use serde::de::{self, Deserialize, Deserializer, SeqAccess, MapAccess};
 use serde_derive::{Serialize, Deserialize};
+use mappings::mcp::McpSide;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[serde(rename = "snake_case")]
@@ -12,6 +13,14 @@ pub enum MappingSystem {
     Srg,
     Mcp,
     Spigot,
+    /// The spigot names, with the server classes in CraftBukkit's versioned package
+    CraftBukkit,
+    /// Mojang's official names, from the ProGuard mappings they publish
+    Mojang,
+    /// Fabric's intermediary names, which are stable across minecraft versions
+    Intermediary,
+    /// Fabric's yarn names, which are mapped from the intermediary names
+    Yarn,
     Obf
 }
 impl MappingSystem {
@@ -19,9 +28,34 @@ impl MappingSystem {
     pub fn is_mcp(self) -> bool {
         match self {
             MappingSystem::Srg | MappingSystem::Mcp => true,
-            MappingSystem::Spigot | MappingSystem::Obf => false,
+            MappingSystem::Spigot | MappingSystem::CraftBukkit |
+            MappingSystem::Mojang | MappingSystem::Intermediary |
+            MappingSystem::Yarn | MappingSystem::Obf => false,
         }
     }
+    #[inline]
+    pub fn is_spigot(self) -> bool {
+        match self {
+            MappingSystem::Spigot | MappingSystem::CraftBukkit => true,
+            MappingSystem::Srg | MappingSystem::Mcp |
+            MappingSystem::Mojang | MappingSystem::Intermediary |
+            MappingSystem::Yarn | MappingSystem::Obf => false,
+        }
+    }
+}
+impl Display for MappingSystem {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+impl FromStr for MappingSystem {
+    type Err = InvalidTarget;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<MappingSystem, InvalidTarget> {
+        MappingSystem::from_id(s).ok_or_else(|| InvalidTarget::MappingSystem(s.into()))
+    }
 }
 impl MappingSystem {
     #[inline]
@@ -30,6 +64,10 @@ impl MappingSystem {
             MappingSystem::Srg => "srg",
             MappingSystem::Mcp => "mcp",
             MappingSystem::Spigot => "spigot",
+            MappingSystem::CraftBukkit => "craftbukkit",
+            MappingSystem::Mojang => "mojang",
+            MappingSystem::Intermediary => "intermediary",
+            MappingSystem::Yarn => "yarn",
             MappingSystem::Obf => "obf",
         }
     }
@@ -38,6 +76,10 @@ impl MappingSystem {
             "srg" => MappingSystem::Srg,
             "mcp" => MappingSystem::Mcp,
             "spigot" => MappingSystem::Spigot,
+            "craftbukkit" => MappingSystem::CraftBukkit,
+            "mojang" => MappingSystem::Mojang,
+            "intermediary" => MappingSystem::Intermediary,
+            "yarn" => MappingSystem::Yarn,
             "obf" => MappingSystem::Obf,
             _ => return None
         })
@@ -69,8 +111,17 @@ impl TargetMapping {
         self.flags = TargetFlags::default();
         self
     }
+    #[inline]
+    pub fn needs_spigot(&self) -> bool {
+        self.original.is_spigot() || self.renamed.is_spigot()
+    }
+    #[inline]
+    pub fn needs_yarn_version(&self) -> bool {
+        self.original == MappingSystem::Yarn || self.renamed == MappingSystem::Yarn
+    }
     pub fn needs_mcp_version(&self) -> bool {
-        self.original.is_mcp() || self.renamed.is_mcp()
+        // We need the MCP data to know which side members are on
+        self.original.is_mcp() || self.renamed.is_mcp() || self.flags.side().is_some()
     }
 }
 impl FromStr for TargetMapping {
@@ -209,12 +260,14 @@ pub struct TargetFlags {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     filter: Option<TargetFilter>,
     #[serde(default, skip_serializing_if = "::std::ops::Not::not")]
-    only_obf: bool
+    only_obf: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    side: Option<TargetSide>
 }
 impl TargetFlags {
     #[inline]
     pub const fn default() -> TargetFlags {
-        TargetFlags { filter: None, only_obf: false }
+        TargetFlags { filter: None, only_obf: false, side: None }
     }
     #[inline]
     pub fn new(classes: bool, members: bool, only_obf: bool) -> TargetFlags {
@@ -224,7 +277,13 @@ impl TargetFlags {
             (true, false) => Some(TargetFilter::Classes),
             (true, true) => panic!("Can't filter both classes and members")
         };
-        TargetFlags { filter, only_obf }
+        TargetFlags { filter, only_obf, side: None }
+    }
+    /// Restrict the target to the members present on the specified side
+    #[inline]
+    pub fn with_side(mut self, side: TargetSide) -> TargetFlags {
+        self.side = Some(side);
+        self
     }
     #[inline]
     pub fn filter(&self) -> Option<TargetFilter> {
@@ -235,6 +294,10 @@ impl TargetFlags {
         self.only_obf
     }
     #[inline]
+    pub fn side(&self) -> Option<TargetSide> {
+        self.side
+    }
+    #[inline]
     pub fn is_default(&self) -> bool {
         *self == TargetFlags::default()
     }
@@ -267,6 +330,14 @@ impl FromStr for TargetFlags {
                     if result.only_obf { return Err(invalid_target()) }
                     result.only_obf = true;
                 },
+                "client" => {
+                    if result.side.is_some() { return Err(invalid_target()) };
+                    result.side = Some(TargetSide::Client);
+                },
+                "server" => {
+                    if result.side.is_some() { return Err(invalid_target()) };
+                    result.side = Some(TargetSide::Server);
+                },
                 _ => return Err(invalid_target())
             }
         }
@@ -284,6 +355,13 @@ impl Display for TargetFlags {
             if self.filter.is_some() { f.write_char('-')? };
             f.write_str("onlyobf")?;
         }
+        if let Some(side) = self.side {
+            if self.filter.is_some() || self.only_obf { f.write_char('-')? };
+            f.write_str(match side {
+                TargetSide::Client => "client",
+                TargetSide::Server => "server",
+            })?;
+        }
         Ok(())
     }
 }
@@ -294,12 +372,31 @@ pub enum TargetFilter {
     Members
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetSide {
+    Client,
+    Server
+}
+impl TargetSide {
+    /// If members on the specified MCP side are present on this side
+    #[inline]
+    pub fn includes(self, side: McpSide) -> bool {
+        match self {
+            TargetSide::Client => side.is_client(),
+            TargetSide::Server => side.is_server(),
+        }
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum InvalidTarget {
     #[fail(display = "Invalid target {:?}", _0)]
     Target(String),
     #[fail(display = "Invalid flags {:?}", _0)]
     Flags(String),
+    #[fail(display = "Invalid mapping system {:?}", _0)]
+    MappingSystem(String),
     #[fail(display = "{}", _0)]
     MinecraftVersion(#[cause] ::mappings::version::InvalidMinecraftVersion)
 }
@@ -323,6 +420,9 @@ mod test {
         assert_eq!(TargetFlags::new(false, true, true), "members-onlyobf".parse().unwrap());
         assert_eq!(TargetFlags::new(true, false, true), "onlyobf-classes".parse().unwrap());
         assert_eq!(TargetFlags::new(false, true, true), "onlyobf-members".parse().unwrap());
+        assert_eq!(TargetFlags::default().with_side(TargetSide::Server), "server".parse().unwrap());
+        assert_eq!(TargetFlags::new(false, true, false).with_side(TargetSide::Client), "members-client".parse().unwrap());
+        assert!("client-server".parse::<TargetFlags>().is_err());
     }
     #[test]
     fn display_flags() {
@@ -332,6 +432,8 @@ mod test {
         assert_eq!(format!("{}", TargetFlags::new(false, true, false)), "members");
         assert_eq!(format!("{}", TargetFlags::new(true, false, true)), "classes-onlyobf");
         assert_eq!(format!("{}", TargetFlags::new(false, true, true)), "members-onlyobf");
+        assert_eq!(format!("{}", TargetFlags::default().with_side(TargetSide::Server)), "server");
+        assert_eq!(format!("{}", TargetFlags::new(true, false, true).with_side(TargetSide::Client)), "classes-onlyobf-client");
     }
     #[test]
     #[should_panic(expected = "Can't filter both classes and members")]
@@ -362,5 +464,25 @@ mod test {
             original: MappingSystem::Spigot,
             renamed: MappingSystem::Mcp,
         }, "spigot2mcp-classes-onlyobf".parse().unwrap());
+        assert_eq!(TargetMapping {
+            flags: TargetFlags::default(),
+            original: MappingSystem::Mcp,
+            renamed: MappingSystem::CraftBukkit,
+        }, "mcp2craftbukkit".parse().unwrap());
+        assert_eq!(TargetMapping {
+            flags: TargetFlags::default(),
+            original: MappingSystem::Spigot,
+            renamed: MappingSystem::Mojang,
+        }, "spigot2mojang".parse().unwrap());
+        assert_eq!(TargetMapping {
+            flags: TargetFlags::default(),
+            original: MappingSystem::Intermediary,
+            renamed: MappingSystem::Srg,
+        }, "intermediary2srg".parse().unwrap());
+        assert_eq!(TargetMapping {
+            flags: TargetFlags::new(false, true, false),
+            original: MappingSystem::Yarn,
+            renamed: MappingSystem::Mcp,
+        }, "yarn2mcp-members".parse().unwrap());
     }
 }
\ No newline at end of file
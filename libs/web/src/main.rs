@@ -13,20 +13,45 @@ use rocket::State;
 use indexmap::IndexMap;
 use serde_derive::{Deserialize, Serialize};
 use rocket_contrib::Json;
-use engine::{TargetMapping, MappingsTargetComputer};
-use mappings::{McpVersionSpec, MinecraftVersion, cache::MinecraftMappingsCache};
-use srglib::prelude::*;
+use engine::{TargetMapping, TargetMetadata, MappingSystem, MappingsTargetComputer, OutputFormat};
+use mappings::{McpVersionSpec, McpVersionRequest, MinecraftVersion, SpigotRevision, YarnVersion, cache::{MinecraftMappingsCache, MemoryStats}};
 
 #[derive(Debug, Deserialize)]
 struct MappingsRequest {
     minecraft_version: MinecraftVersion,
     #[serde(default)]
-    mcp_version: Option<McpVersionSpec>,
-    targets: Vec<TargetMapping>
+    mcp_version: Option<McpVersionRequest>,
+    targets: Vec<TargetMapping>,
+    /// Allow a MCP version exported for a different minecraft version
+    #[serde(default)]
+    cross_version_mcp: bool,
+    /// The revision of spigot's mappings to use, instead of the latest one
+    #[serde(default)]
+    spigot_revision: Option<SpigotRevision>,
+    /// The build of yarn to use, which defaults to the latest one
+    #[serde(default)]
+    yarn_version: Option<YarnVersion>,
+    /// The format to serialize the mappings in, which defaults to SRG
+    #[serde(default)]
+    format: OutputFormat,
+    /// Include the MCP documentation of each target's members
+    #[serde(default)]
+    include_metadata: bool
 }
 #[derive(Debug, Serialize)]
 struct MappingsResponse {
+    /// The exact MCP version we resolved the request to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mcp_version: Option<McpVersionSpec>,
+    /// The yarn build we used, if any of the targets needed it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    yarn_version: Option<YarnVersion>,
     serialized_mappings: IndexMap<TargetMapping, String>,
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    metadata: IndexMap<TargetMapping, TargetMetadata>,
+    /// The number of srg names that had no MCP name, if we computed any MCP names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missing_mcp_names: Option<usize>,
     /// The total resposne time in milliseconds
     response_time: u64
 }
@@ -35,20 +60,56 @@ struct MappingsResponse {
 fn load_mappings(cache: State<MinecraftMappingsCache>, request: Json<MappingsRequest>) -> Result<Json<MappingsResponse>, Error> {
     let start = Instant::now();
     let request: &MappingsRequest = &request.0; // TODO: IntelliJ can't handle the defualt type paramter
+    let mcp_version = match request.mcp_version {
+        Some(mcp_request) => Some(cache.resolve_mcp_version(mcp_request, request.minecraft_version)?),
+        None => None
+    };
+    let yarn_version = match request.yarn_version {
+        Some(version) => Some(version),
+        None if request.targets.iter().any(TargetMapping::needs_yarn_version) => {
+            Some(cache.resolve_yarn_version(request.minecraft_version)?)
+        },
+        None => None
+    };
     let computer = MappingsTargetComputer::new(
         &cache,
         request.minecraft_version,
-        request.mcp_version.map(|version| version.version)
-    );
+        mcp_version
+    ).cross_version_mcp(request.cross_version_mcp)
+        .spigot_revision(request.spigot_revision.clone())
+        .yarn_version(yarn_version);
     let mut serialized_mappings =
         IndexMap::with_capacity(request.targets.len());
     for &target in &request.targets {
-        let mappings = computer.compute_target(target)?;
-        let serialized = SrgMappingsFormat::write_string(&mappings);
-        serialized_mappings.insert(target, serialized);
+        let mappings = computer.compute_mapped_target(target)?;
+        let mut serialized = Vec::new();
+        request.format.write_mapped(&mappings, &mut serialized)?;
+        serialized_mappings.insert(target, String::from_utf8(serialized)?);
     }
+    let mut metadata = IndexMap::new();
+    if request.include_metadata {
+        for &target in &request.targets {
+            metadata.insert(target, computer.compute_metadata(target)?);
+        }
+    }
+    // The targets may have been loaded from their mapped stores, so we can't rely on them counting it
+    let missing_mcp_names = if request.targets.iter().any(|target| {
+        target.original == MappingSystem::Mcp || target.renamed == MappingSystem::Mcp
+    }) {
+        Some(computer.count_missing_mcp_names()?)
+    } else {
+        None
+    };
     let response_time = to_millis(start.elapsed());
-    Ok(Json(MappingsResponse { serialized_mappings, response_time }))
+    Ok(Json(MappingsResponse {
+        mcp_version, yarn_version, serialized_mappings, metadata,
+        missing_mcp_names, response_time
+    }))
+}
+/// The hit rates and sizes of the in-memory caches, for monitoring the server
+#[get("/api/beta/memory_stats")]
+fn memory_stats(cache: State<MinecraftMappingsCache>) -> Json<MemoryStats> {
+    Json(cache.memory_stats())
 }
 fn to_millis(d: Duration) -> u64 {
     d.as_secs().saturating_mul(1000)
@@ -60,6 +121,6 @@ fn main() {
         .expect("Unable to setup cache");
     rocket::ignite()
         .manage(cache)
-        .mount("/", routes![load_mappings])
+        .mount("/", routes![load_mappings, memory_stats])
         .launch();
 }
\ No newline at end of file